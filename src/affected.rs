@@ -0,0 +1,262 @@
+use crate::build::build_types::{BuildState, Module};
+use crate::helpers;
+use ahash::{AHashMap, AHashSet};
+use serde_json::json;
+use std::collections::BTreeSet;
+use std::io::BufRead;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+// Finds the modules (and their packages) that need to be compiled again after some files changed,
+// so that CI only needs to run the test suites of what is affected by a change.
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Affected {
+    // the modules of the changed files
+    pub changed_modules: BTreeSet<String>,
+    // the changed modules and all modules that depend on them, transitively
+    pub modules: BTreeSet<String>,
+    pub packages: BTreeSet<String>,
+}
+
+impl Affected {
+    pub fn to_json(&self) -> serde_json::Value {
+        json!({
+            "changed_modules": self.changed_modules,
+            "modules": self.modules,
+            "packages": self.packages,
+        })
+    }
+}
+
+/// The folder that the paths of `git diff --name-only` are relative to: the top level of the git
+/// repository that the folder is in, or the current directory outside of git
+pub fn get_changed_files_base(folder: &str) -> std::io::Result<PathBuf> {
+    let toplevel = Command::new("git")
+        .current_dir(folder)
+        .args(["rev-parse", "--show-toplevel"])
+        .output()
+        .ok()
+        .filter(|output| output.status.success())
+        .map(|output| String::from_utf8_lossy(&output.stdout).trim().to_string())
+        .filter(|toplevel| !toplevel.is_empty());
+    match toplevel {
+        Some(toplevel) => Ok(PathBuf::from(toplevel)),
+        None => std::env::current_dir(),
+    }
+}
+
+/// Reads the changed files, one per line, as printed by `git diff --name-only`. Relative paths are
+/// relative to `base`.
+pub fn read_changed_files(reader: impl BufRead, base: &Path) -> Vec<String> {
+    reader
+        .lines()
+        .map_while(Result::ok)
+        .map(|line| line.trim().to_string())
+        .filter(|line| !line.is_empty())
+        .map(|line| helpers::get_abs_path(&base.join(line).to_string_lossy()))
+        .collect()
+}
+
+fn run_git(folder: &str, since: &str, args: &[&str]) -> Result<Vec<String>, String> {
+    let output = Command::new("git")
+        .current_dir(folder)
+        .args(args)
+        .output()
+        .map_err(|e| format!("Could not run git: {}", e))?;
+    if !output.status.success() {
+        return Err(format!(
+            "Could not get the files that changed since {}: {}",
+            since,
+            String::from_utf8_lossy(&output.stderr).trim()
+        ));
+    }
+    Ok(read_changed_files(output.stdout.as_slice(), Path::new(folder)))
+}
+
+/// The files that changed since the git revision, including the changes that are not committed yet
+/// and the new files that are not added to git yet
+pub fn get_changed_files_since(folder: &str, since: &str) -> Result<Vec<String>, String> {
+    let mut changed_files = run_git(folder, since, &["diff", "--name-only", "--relative", since, "--"])?;
+    changed_files.extend(run_git(
+        folder,
+        since,
+        &["ls-files", "--others", "--exclude-standard"],
+    )?);
+    Ok(changed_files)
+}
+
+// the modules of the changed files. A changed config affects all modules of its package. A file
+// that was deleted has no module anymore, but the modules that used it still have it as a
+// dependency.
+fn get_changed_modules(build_state: &BuildState, changed_files: &[String]) -> AHashSet<String> {
+    let changed_files = changed_files.iter().collect::<AHashSet<&String>>();
+    let mut changed_modules = AHashSet::new();
+    for package in build_state.packages.values() {
+        let package_path = Path::new(&package.path);
+        let is_changed =
+            |path: &Path| changed_files.contains(&helpers::get_abs_path(&path.to_string_lossy()));
+
+        if is_changed(&package_path.join("rescript.json")) || is_changed(&package_path.join("bsconfig.json"))
+        {
+            changed_modules.extend(
+                build_state
+                    .modules
+                    .iter()
+                    .filter(|(_, module)| module.package_name == package.name)
+                    .map(|(module_name, _)| module_name.to_owned()),
+            );
+        }
+
+        changed_modules.extend(
+            changed_files
+                .iter()
+                .filter_map(|changed_file| Path::new(changed_file).strip_prefix(package_path).ok())
+                .filter(|relative_path| {
+                    relative_path
+                        .extension()
+                        .and_then(|extension| extension.to_str())
+                        .is_some_and(helpers::is_source_file)
+                })
+                .map(|relative_path| {
+                    helpers::file_path_to_module_name(&relative_path.to_string_lossy(), &package.namespace)
+                })
+                .filter(|module_name| {
+                    build_state.modules.contains_key(module_name)
+                        || build_state.deleted_modules.contains(module_name)
+                }),
+        );
+    }
+    changed_modules
+}
+
+/// Expands the changed modules with their dependents, the same way as the compile universe is built
+/// when compiling
+pub fn get_affected_modules(
+    modules: &AHashMap<String, Module>,
+    changed_modules: &AHashSet<String>,
+) -> AHashSet<String> {
+    let mut affected_modules = changed_modules.clone();
+    let mut current_step_modules = changed_modules.clone();
+    loop {
+        let mut dependents: AHashSet<String> = AHashSet::new();
+        for module_name in current_step_modules.iter() {
+            match modules.get(module_name) {
+                Some(module) => dependents.extend(module.dependents.iter().cloned()),
+                // a deleted module
+                None => dependents.extend(
+                    modules
+                        .iter()
+                        .filter(|(_, module)| module.deps.contains(module_name))
+                        .map(|(module_name, _)| module_name.to_owned()),
+                ),
+            }
+        }
+
+        current_step_modules = dependents
+            .difference(&affected_modules)
+            .map(|s| s.to_string())
+            .collect::<AHashSet<String>>();
+
+        affected_modules.extend(current_step_modules.to_owned());
+        if current_step_modules.is_empty() {
+            break;
+        }
+    }
+    affected_modules
+}
+
+pub fn find(build_state: &BuildState, changed_files: &[String]) -> Affected {
+    let changed_modules = get_changed_modules(build_state, changed_files);
+    let modules = get_affected_modules(&build_state.modules, &changed_modules)
+        .into_iter()
+        .filter(|module_name| build_state.modules.contains_key(module_name))
+        .collect::<BTreeSet<String>>();
+    let packages = modules
+        .iter()
+        .map(|module_name| build_state.modules[module_name].package_name.to_owned())
+        .collect::<BTreeSet<String>>();
+    Affected {
+        changed_modules: changed_modules.into_iter().collect(),
+        modules,
+        packages,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::build::build_types::{MlMap, SourceType};
+
+    fn create_module(deps: &[&str], dependents: &[&str]) -> Module {
+        Module {
+            source_type: SourceType::MlMap(MlMap { parse_dirty: false }),
+            deps: deps.iter().map(|dep| dep.to_string()).collect(),
+            dependents: dependents.iter().map(|dependent| dependent.to_string()).collect(),
+            package_name: "main".to_string(),
+            is_type_dev: false,
+            compile_dirty: false,
+            dirty_reason: None,
+            last_compiled_cmi: None,
+            last_compiled_cmt: None,
+            deps_dirty: false,
+        }
+    }
+
+    #[test]
+    fn test_get_affected_modules() {
+        let modules = AHashMap::from([
+            ("App".to_string(), create_module(&["Button", "Removed"], &[])),
+            ("Button".to_string(), create_module(&["Utils"], &["App"])),
+            ("Utils".to_string(), create_module(&[], &["Button"])),
+            ("Other".to_string(), create_module(&[], &[])),
+        ]);
+        let sorted = |modules: AHashSet<String>| modules.into_iter().collect::<BTreeSet<String>>();
+
+        assert_eq!(
+            sorted(get_affected_modules(
+                &modules,
+                &AHashSet::from(["Utils".to_string()])
+            )),
+            BTreeSet::from(["App".to_string(), "Button".to_string(), "Utils".to_string()])
+        );
+        assert_eq!(
+            sorted(get_affected_modules(
+                &modules,
+                &AHashSet::from(["Removed".to_string()])
+            )),
+            BTreeSet::from(["App".to_string(), "Removed".to_string()])
+        );
+    }
+
+    #[test]
+    fn test_read_changed_files() {
+        let input = "src/App.res\n\n  src/Button.resi \n";
+        assert_eq!(
+            read_changed_files(input.as_bytes(), Path::new("/project")),
+            vec!["/project/src/App.res", "/project/src/Button.resi"]
+        );
+    }
+
+    #[test]
+    fn test_get_changed_files_base() {
+        // git diff prints the paths relative to the top level of the repository, also in a package
+        let root = std::env::temp_dir().join(format!("rewatch-changed-files-base-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&root);
+        std::fs::create_dir_all(root.join("packages/ui")).unwrap();
+        let root = root.canonicalize().unwrap();
+        let initialized = Command::new("git")
+            .current_dir(&root)
+            .args(["init", "-q"])
+            .status()
+            .is_ok_and(|status| status.success());
+
+        if initialized {
+            assert_eq!(
+                get_changed_files_base(&root.join("packages/ui").to_string_lossy()).unwrap(),
+                root
+            );
+        }
+        let _ = std::fs::remove_dir_all(&root);
+    }
+}
@@ -1,22 +1,30 @@
+pub mod artifact_cache;
 pub mod build_types;
 pub mod clean;
 pub mod compile;
 pub mod deps;
+pub mod diagnostics;
+pub mod dry_run;
+pub mod generators;
 pub mod logs;
 pub mod namespaces;
 pub mod packages;
 pub mod parse;
+pub mod profile;
 pub mod read_compile_state;
+pub mod state_cache;
 
 use crate::build::compile::{mark_modules_with_deleted_deps_dirty, mark_modules_with_expired_deps_dirty};
 use crate::helpers::emojis::*;
 use crate::helpers::{self, get_workspace_root};
+use crate::sarif;
 use crate::sourcedirs;
+use ahash::{AHashMap, AHashSet};
 use anyhow::{anyhow, Result};
 use build_types::*;
 use console::style;
 use indicatif::{ProgressBar, ProgressStyle};
-use log::log_enabled;
+use log::{debug, log_enabled};
 use serde::Serialize;
 use std::fmt;
 use std::fs::File;
@@ -127,6 +135,10 @@ pub fn get_compiler_args(
     Ok(result)
 }
 
+/// Finds the packages and their source files, and reads the state of the previous build. With
+/// `read_only` (for a dry run, or to only get the dependencies), the state of the previous build
+/// is kept and the stale compiler assets are not removed.
+#[allow(clippy::too_many_arguments)]
 pub fn initialize_build(
     default_timing: Option<Duration>,
     filter: &Option<regex::Regex>,
@@ -134,7 +146,10 @@ pub fn initialize_build(
     path: &str,
     bsc_path: Option<String>,
     build_dev_deps: bool,
+    read_only: bool,
+    profile: bool,
 ) -> Result<BuildState> {
+    let timing_initialize = Instant::now();
     let project_root = helpers::get_abs_path(path);
     let workspace_root = helpers::get_workspace_root(&project_root);
     let bsc_path = match bsc_path {
@@ -196,6 +211,22 @@ pub fn initialize_build(
     );
     packages::parse_packages(&mut build_state);
     let timing_source_files_elapsed = timing_source_files.elapsed();
+    if profile {
+        let profile = profile::Profile::new(timing_initialize);
+        profile.record(
+            "Build package tree",
+            "phase",
+            timing_package_tree,
+            timing_package_tree_elapsed,
+        );
+        profile.record(
+            "Find source files",
+            "phase",
+            timing_source_files,
+            timing_source_files_elapsed,
+        );
+        build_state.profile = Some(profile);
+    }
 
     if show_progress {
         println!(
@@ -216,8 +247,24 @@ pub fn initialize_build(
         let _ = stdout().flush();
     }
     let timing_compile_state = Instant::now();
-    let compile_assets_state = read_compile_state::read(&mut build_state);
+    // when the state of the previous build can be restored, we don't need to scan the compiler assets
+    let previous_build = state_cache::read(&build_state, build_dev_deps, read_only);
+    let cached_source_files = previous_build
+        .as_ref()
+        .and_then(|previous_build| state_cache::restore(&mut build_state, previous_build));
+    let compile_assets_state = match cached_source_files {
+        Some(_) => None,
+        None => Some(read_compile_state::read(&mut build_state)),
+    };
     let timing_compile_state_elapsed = timing_compile_state.elapsed();
+    if let Some(profile) = &build_state.profile {
+        profile.record(
+            "Read compile state",
+            "phase",
+            timing_compile_state,
+            timing_compile_state_elapsed,
+        );
+    }
 
     if show_progress {
         println!(
@@ -237,8 +284,25 @@ pub fn initialize_build(
         );
     }
     let timing_cleanup = Instant::now();
-    let (diff_cleanup, total_cleanup) = clean::cleanup_previous_build(&mut build_state, compile_assets_state);
+    let (diff_cleanup, total_cleanup) = match (compile_assets_state, cached_source_files) {
+        (Some(compile_assets_state), _) => {
+            let result = clean::cleanup_previous_build(&mut build_state, compile_assets_state, read_only);
+            if let Some(previous_build) = &previous_build {
+                state_cache::restore_hashes(&mut build_state, previous_build);
+            }
+            result
+        }
+        (None, num_source_files) => (0, num_source_files.unwrap_or(0)),
+    };
     let timing_cleanup_elapsed = timing_cleanup.elapsed();
+    if let Some(profile) = &build_state.profile {
+        profile.record(
+            "Clean up previous build",
+            "phase",
+            timing_cleanup,
+            timing_cleanup_elapsed,
+        );
+    }
 
     if show_progress {
         println!(
@@ -255,20 +319,37 @@ pub fn initialize_build(
     Ok(build_state)
 }
 
+// errors that are not diagnostics of bsc, with the json reporter we keep stdout for the diagnostics
+fn print_error(reporter: diagnostics::Reporter, error: &str) {
+    if reporter.is_json() {
+        eprintln!("{}", error);
+    } else {
+        println!("{}", error);
+    }
+}
+
 fn format_step(current: usize, total: usize) -> console::StyledObject<String> {
     style(format!("[{}/{}]", current, total)).bold().dim()
 }
 
 #[derive(Debug, Clone)]
 pub enum IncrementalBuildError {
+    GeneratorError,
     SourceFileParseError,
+    DependencyError,
     CompileError(Option<String>),
 }
 
 impl fmt::Display for IncrementalBuildError {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         match self {
+            Self::GeneratorError => write!(f, "{}  {}Could not run Generators", LINE_CLEAR, CROSS,),
             Self::SourceFileParseError => write!(f, "{}  {}Could not parse Source Files", LINE_CLEAR, CROSS,),
+            Self::DependencyError => write!(
+                f,
+                "{}  {}Found invalid dependencies. See Errors Above",
+                LINE_CLEAR, CROSS,
+            ),
             Self::CompileError(Some(e)) => {
                 write!(f, "{}  {}Failed to Compile. Error: {e}", LINE_CLEAR, CROSS,)
             }
@@ -279,6 +360,7 @@ impl fmt::Display for IncrementalBuildError {
     }
 }
 
+#[allow(clippy::too_many_arguments)]
 pub fn incremental_build(
     build_state: &mut BuildState,
     default_timing: Option<Duration>,
@@ -287,8 +369,36 @@ pub fn incremental_build(
     only_incremental: bool,
     create_sourcedirs: bool,
     build_dev_deps: bool,
+    reporter: diagnostics::Reporter,
 ) -> Result<(), IncrementalBuildError> {
     logs::initialize(&build_state.packages);
+    build_state.diagnostics.clear();
+    build_state.compiled_modules.clear();
+
+    let timing_generators = Instant::now();
+    match generators::run(build_state, build_dev_deps) {
+        Ok(0) => (),
+        Ok(num_generators) => {
+            if show_progress {
+                println!(
+                    "{}{} {}Ran {} generators in {:.2}s",
+                    LINE_CLEAR,
+                    style("[...]").bold().dim(),
+                    COMMAND,
+                    num_generators,
+                    default_timing
+                        .unwrap_or(timing_generators.elapsed())
+                        .as_secs_f64()
+                );
+            }
+        }
+        Err(err) => {
+            logs::finalize(&build_state.packages);
+            print_error(reporter, &format!("Could not run generators: {}", &err));
+            return Err(IncrementalBuildError::GeneratorError);
+        }
+    }
+
     let num_dirty_modules = build_state.modules.values().filter(|m| is_dirty(m)).count() as u64;
     let pb = if show_progress {
         ProgressBar::new(num_dirty_modules)
@@ -309,6 +419,9 @@ pub fn incremental_build(
     let timing_ast = Instant::now();
     let result_asts = parse::generate_asts(build_state, || pb.inc(1));
     let timing_ast_elapsed = timing_ast.elapsed();
+    if let Some(profile) = &build_state.profile {
+        profile.record("Parse", "phase", timing_ast, timing_ast_elapsed);
+    }
 
     match result_asts {
         Ok(_ast) => {
@@ -337,34 +450,64 @@ pub fn incremental_build(
                 pb.finish();
             }
 
-            println!("Could not parse source files: {}", &err);
+            if !reporter.is_json() {
+                println!("Could not parse source files: {}", &err);
+            }
             return Err(IncrementalBuildError::SourceFileParseError);
         }
     }
     let timing_deps = Instant::now();
-    deps::get_deps(build_state, &build_state.deleted_modules.to_owned());
+    let result_deps = deps::get_deps(build_state, &build_state.deleted_modules.to_owned());
     let timing_deps_elapsed = timing_deps.elapsed();
+    if let Some(profile) = &build_state.profile {
+        profile.record("Collect deps", "phase", timing_deps, timing_deps_elapsed);
+    }
     current_step += 1;
 
-    if show_progress {
-        println!(
-            "{}{} {}Collected deps in {:.2}s",
-            LINE_CLEAR,
-            format_step(current_step, total_steps),
-            DEPS,
-            default_timing.unwrap_or(timing_deps_elapsed).as_secs_f64()
-        );
+    match result_deps {
+        Ok(_) => {
+            if show_progress {
+                println!(
+                    "{}{} {}Collected deps in {:.2}s",
+                    LINE_CLEAR,
+                    format_step(current_step, total_steps),
+                    DEPS,
+                    default_timing.unwrap_or(timing_deps_elapsed).as_secs_f64()
+                );
+            }
+        }
+        Err(err) => {
+            logs::finalize(&build_state.packages);
+            if show_progress {
+                println!(
+                    "{}{} {}Error collecting deps in {:.2}s",
+                    LINE_CLEAR,
+                    format_step(current_step, total_steps),
+                    CROSS,
+                    default_timing.unwrap_or(timing_deps_elapsed).as_secs_f64()
+                );
+            }
+
+            print_error(reporter, &err);
+            return Err(IncrementalBuildError::DependencyError);
+        }
     }
 
+    if let Some(entries) = build_state.entries.to_owned() {
+        retain_entry_modules(build_state, &entries);
+    }
     mark_modules_with_expired_deps_dirty(build_state);
     mark_modules_with_deleted_deps_dirty(build_state);
     current_step += 1;
 
-    //print all the compile_dirty modules
-    if log_enabled!(log::Level::Trace) {
+    //print all the compile_dirty modules, and why they are dirty
+    if log_enabled!(log::Level::Debug) {
         for (module_name, module) in build_state.modules.iter() {
             if module.compile_dirty {
-                println!("compile dirty: {}", module_name);
+                match &module.dirty_reason {
+                    Some(dirty_reason) => debug!("Compile dirty: {}: {}", module_name, dirty_reason),
+                    None => debug!("Compile dirty: {}", module_name),
+                }
             }
         }
     };
@@ -394,6 +537,9 @@ pub fn incremental_build(
     .map_err(|e| IncrementalBuildError::CompileError(Some(e.to_string())))?;
 
     let compile_duration = start_compiling.elapsed();
+    if let Some(profile) = &build_state.profile {
+        profile.record("Compile", "phase", start_compiling, compile_duration);
+    }
 
     logs::finalize(&build_state.packages);
     if create_sourcedirs {
@@ -411,10 +557,10 @@ pub fn incremental_build(
                 default_timing.unwrap_or(compile_duration).as_secs_f64()
             );
         }
-        if helpers::contains_ascii_characters(&compile_warnings) {
+        if !reporter.is_json() && helpers::contains_ascii_characters(&compile_warnings) {
             println!("{}", &compile_warnings);
         }
-        if helpers::contains_ascii_characters(&compile_errors) {
+        if !reporter.is_json() && helpers::contains_ascii_characters(&compile_errors) {
             println!("{}", &compile_errors);
         }
         Err(IncrementalBuildError::CompileError(None))
@@ -430,13 +576,170 @@ pub fn incremental_build(
             );
         }
 
-        if helpers::contains_ascii_characters(&compile_warnings) {
+        if !reporter.is_json() && helpers::contains_ascii_characters(&compile_warnings) {
             println!("{}", &compile_warnings);
         }
         Ok(())
     }
 }
 
+/// The modules that were compiled in the last incremental build, in the order they were compiled,
+/// with the reason
+pub fn format_compiled_modules(build_state: &BuildState) -> String {
+    if build_state.compiled_modules.is_empty() {
+        return "No modules were compiled".to_string();
+    }
+    let compiled_modules = build_state
+        .compiled_modules
+        .iter()
+        .map(|(module_name, dirty_reason)| {
+            format!(
+                "  {}: {}",
+                helpers::format_namespaced_module_name(module_name),
+                dirty_reason
+            )
+        })
+        .collect::<Vec<String>>()
+        .join("\n");
+    format!(
+        "Compiled {} modules:\n{}",
+        build_state.compiled_modules.len(),
+        compiled_modules
+    )
+}
+
+/// Restricts the build to the given packages and the packages they depend on. The other packages
+/// are not parsed or compiled, and their compiler assets are left as they are. The root package
+/// always stays, because its config applies to all packages, but its modules are only built when
+/// it is selected.
+pub fn retain_packages(
+    build_state: &mut BuildState,
+    package_names: &[String],
+    build_dev_deps: bool,
+) -> Result<()> {
+    let closure = packages::get_dependency_closure(&build_state.packages, package_names, build_dev_deps)
+        .map_err(|e| anyhow!(e))?;
+    let root_config_name = build_state.root_config_name.to_owned();
+    build_state
+        .packages
+        .retain(|package_name, _| closure.contains(package_name) || package_name == &root_config_name);
+    if !closure.contains(&root_config_name) {
+        if let Some(root_package) = build_state.packages.get_mut(&root_config_name) {
+            root_package.source_folders.clear();
+            root_package.source_files = Some(AHashMap::new());
+            root_package.modules = Some(AHashSet::new());
+            root_package.dirs = Some(AHashSet::new());
+        }
+    }
+    build_state
+        .modules
+        .retain(|_, module| closure.contains(&module.package_name));
+    build_state.module_names = build_state
+        .packages
+        .values()
+        .flat_map(|package| package.modules.to_owned().unwrap_or_default())
+        .collect();
+    Ok(())
+}
+
+/// The entry modules of the build: the modules that are given with --entry, or else the
+/// `entries` in the config of the root package. A namespaced module can be given as
+/// `Namespace.Module`. Returns None when the build has no entries, then all modules are built.
+pub fn get_entries(build_state: &BuildState, entries: &[String]) -> Result<Option<Vec<String>>> {
+    let root_package = build_state
+        .get_package(&build_state.root_config_name)
+        .expect("Could not find root package");
+    let entries = if !entries.is_empty() {
+        entries.to_vec()
+    } else if build_state
+        .modules
+        .values()
+        .any(|module| module.package_name == root_package.name)
+    {
+        root_package
+            .config
+            .get_entry_modules()
+            .iter()
+            .map(|entry| helpers::file_path_to_module_name(entry, &root_package.namespace))
+            .collect()
+    } else {
+        vec![]
+    };
+    if entries.is_empty() {
+        return Ok(None);
+    }
+
+    entries
+        .iter()
+        .map(|entry| {
+            if build_state.modules.contains_key(entry) {
+                return Ok(entry.to_owned());
+            }
+            build_state
+                .modules
+                .keys()
+                .find(|module_name| helpers::format_namespaced_module_name(module_name) == *entry)
+                .cloned()
+                .ok_or(anyhow!("Could not find the entry module {}", entry))
+        })
+        .collect::<Result<Vec<String>>>()
+        .map(Some)
+}
+
+/// Only keeps the entry modules and the modules they depend on, transitively, so that the other
+/// modules are not compiled. The namespace of a package is kept, but not the modules in it.
+fn retain_entry_modules(build_state: &mut BuildState, entries: &[String]) {
+    let mut modules = AHashSet::new();
+    let mut queue = entries.to_vec();
+    while let Some(module_name) = queue.pop() {
+        if let Some(module) = build_state.get_module(&module_name) {
+            if modules.insert(module_name) && !module.is_mlmap() {
+                queue.extend(module.deps.iter().cloned());
+                if let Some(namespace) = build_state
+                    .get_package(&module.package_name)
+                    .and_then(|package| package.namespace.to_suffix())
+                {
+                    queue.push(namespace);
+                }
+            }
+        }
+    }
+
+    build_state
+        .modules
+        .retain(|module_name, _| modules.contains(module_name));
+    build_state
+        .modules
+        .values_mut()
+        .for_each(|module| module.dependents.retain(|dependent| modules.contains(dependent)));
+    build_state
+        .module_names
+        .retain(|module_name| modules.contains(module_name));
+}
+
+/// Parses the sources and collects the dependencies of all modules, without compiling them. This
+/// is enough for the commands that inspect the dependency graph.
+pub fn get_dependency_state(
+    path: &str,
+    bsc_path: Option<String>,
+    build_dev_deps: bool,
+) -> Result<BuildState> {
+    // the state of the previous build is kept for the next build, this doesn't compile anything
+    let mut build_state = initialize_build(None, &None, false, path, bsc_path, build_dev_deps, true, false)
+        .map_err(|e| anyhow!("Could not initialize build. Error: {e}"))?;
+
+    logs::initialize(&build_state.packages);
+    let result = parse::generate_asts(&mut build_state, || ())
+        .map_err(|e| anyhow!("Could not parse source files: {e}"))
+        .and_then(|_| {
+            let deleted_modules = build_state.deleted_modules.to_owned();
+            deps::get_deps(&mut build_state, &deleted_modules).map_err(|e| anyhow!(e))
+        });
+    logs::finalize(&build_state.packages);
+
+    result.map(|_| build_state)
+}
+
 // write build.ninja files in the packages after a non-incremental build
 // this is necessary to bust the editor tooling cache. The editor tooling
 // is watching this file.
@@ -451,6 +754,7 @@ pub fn write_build_ninja(build_state: &BuildState) {
     }
 }
 
+#[allow(clippy::too_many_arguments)]
 pub fn build(
     filter: &Option<regex::Regex>,
     path: &str,
@@ -459,6 +763,13 @@ pub fn build(
     create_sourcedirs: bool,
     bsc_path: Option<String>,
     build_dev_deps: bool,
+    reporter: diagnostics::Reporter,
+    sarif_path: Option<String>,
+    artifact_cache: Option<String>,
+    packages: &[String],
+    entries: &[String],
+    explain: bool,
+    profile_path: Option<String>,
 ) -> Result<BuildState> {
     let default_timing: Option<std::time::Duration> = if no_timing {
         Some(std::time::Duration::new(0.0 as u64, 0.0 as u32))
@@ -473,18 +784,49 @@ pub fn build(
         path,
         bsc_path,
         build_dev_deps,
+        false,
+        profile_path.is_some(),
     )
     .map_err(|e| anyhow!("Could not initialize build. Error: {e}"))?;
+    if !packages.is_empty() {
+        // the .sourcedirs.json lists all packages, also the ones that are not built
+        if create_sourcedirs {
+            sourcedirs::print(&build_state);
+        }
+        retain_packages(&mut build_state, packages, build_dev_deps)?;
+    }
+    build_state.entries = get_entries(&build_state, entries)?;
+    // the state of a build of part of the modules can't be restored for a full build, so it's not
+    // written. The next build reads the compile state from the compiler assets instead.
+    let is_partial_build = !packages.is_empty() || build_state.entries.is_some();
+    if let Some(location) = artifact_cache {
+        build_state.artifact_cache =
+            Some(artifact_cache::ArtifactCache::new(&location).map_err(|e| anyhow!(e))?);
+    }
 
-    match incremental_build(
+    let result = incremental_build(
         &mut build_state,
         default_timing,
         true,
         show_progress,
         false,
-        create_sourcedirs,
+        create_sourcedirs && packages.is_empty(),
         build_dev_deps,
-    ) {
+        reporter,
+    );
+    if reporter.is_json() {
+        diagnostics::print_json(&build_state.diagnostics);
+    } else if explain {
+        println!("{}", format_compiled_modules(&build_state));
+    }
+    if let Some(sarif_path) = sarif_path {
+        sarif::write(&build_state, &sarif_path);
+    }
+    if let (Some(profile_path), Some(profile)) = (profile_path, &build_state.profile) {
+        profile::write(profile, &profile_path);
+    }
+
+    match result {
         Ok(_) => {
             if show_progress {
                 let timing_total_elapsed = timing_total.elapsed();
@@ -497,11 +839,17 @@ pub fn build(
             }
             clean::cleanup_after_build(&build_state);
             write_build_ninja(&build_state);
+            if !is_partial_build {
+                state_cache::write(&build_state, build_dev_deps);
+            }
             Ok(build_state)
         }
         Err(e) => {
             clean::cleanup_after_build(&build_state);
             write_build_ninja(&build_state);
+            if !is_partial_build {
+                state_cache::write(&build_state, build_dev_deps);
+            }
             Err(anyhow!("Incremental build failed. Error: {e}"))
         }
     }
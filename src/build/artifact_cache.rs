@@ -0,0 +1,555 @@
+use super::build_types::*;
+use crate::helpers;
+use log::debug;
+use std::fmt;
+use std::fs;
+use std::io::{Read, Write};
+use std::net::TcpStream;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::time::Duration;
+
+// A cache for the artifacts of bsc that can be shared between builds, for instance between CI
+// jobs that build the same (dependency) packages over and over again. The outputs of parsing or
+// compiling a file are stored under a key that is a hash of all inputs of the invocation of bsc:
+// the sources, the arguments, the version of the compiler and the .cmi files of the dependencies.
+// When the key is found, the outputs are restored instead of running bsc.
+//
+// The artifacts are stored in a local directory, or in an HTTP server that supports
+// `GET <url>/<key>` and `PUT <url>/<key>` (for instance a generic object store or a build cache
+// server).
+
+// bump this when the layout of the stored artifacts changes
+static CACHE_VERSION: &str = "1";
+static HTTP_TIMEOUT: Duration = Duration::from_secs(10);
+
+pub trait Backend: fmt::Debug + Send + Sync {
+    fn get(&self, key: &str) -> Option<Vec<u8>>;
+    fn put(&self, key: &str, data: &[u8]);
+}
+
+#[derive(Debug)]
+pub struct LocalBackend {
+    path: PathBuf,
+}
+
+static TEMP_FILE_COUNTER: AtomicUsize = AtomicUsize::new(0);
+
+impl Backend for LocalBackend {
+    fn get(&self, key: &str) -> Option<Vec<u8>> {
+        fs::read(self.path.join(key)).ok()
+    }
+
+    fn put(&self, key: &str, data: &[u8]) {
+        // write to a temporary file first, so other builds sharing the directory never see a
+        // partially written artifact
+        let temp_path = self.path.join(format!(
+            "{}.{}-{}.tmp",
+            key,
+            std::process::id(),
+            TEMP_FILE_COUNTER.fetch_add(1, Ordering::Relaxed)
+        ));
+        let result = fs::write(&temp_path, data).and_then(|_| fs::rename(&temp_path, self.path.join(key)));
+        if let Err(e) = result {
+            debug!("Could not write {} to the artifact cache: {}", key, e);
+            let _ = fs::remove_file(&temp_path);
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct HttpBackend {
+    host: String,
+    path: String,
+}
+
+impl HttpBackend {
+    fn new(url: &str) -> Result<Self, String> {
+        let without_scheme = url.strip_prefix("http://").ok_or(format!(
+            "Only http:// urls are supported for the artifact cache: {}",
+            url
+        ))?;
+        let (host, path) = match without_scheme.find('/') {
+            Some(index) => (&without_scheme[..index], &without_scheme[index..]),
+            None => (without_scheme, ""),
+        };
+        if host.is_empty() {
+            return Err(format!("Missing host in the url of the artifact cache: {}", url));
+        }
+        Ok(Self {
+            host: host.to_string(),
+            path: path.trim_end_matches('/').to_string(),
+        })
+    }
+
+    fn get_address(&self) -> String {
+        if self.host.contains(':') {
+            self.host.to_owned()
+        } else {
+            format!("{}:80", self.host)
+        }
+    }
+
+    fn request(&self, method: &str, key: &str, body: &[u8]) -> std::io::Result<(u16, Vec<u8>)> {
+        let mut stream = TcpStream::connect(self.get_address())?;
+        stream.set_read_timeout(Some(HTTP_TIMEOUT))?;
+        stream.set_write_timeout(Some(HTTP_TIMEOUT))?;
+        write!(
+            stream,
+            "{} {}/{} HTTP/1.1\r\nHost: {}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n",
+            method,
+            self.path,
+            key,
+            self.host,
+            body.len()
+        )?;
+        stream.write_all(body)?;
+        stream.flush()?;
+
+        let mut response = vec![];
+        stream.read_to_end(&mut response)?;
+        parse_response(&response)
+            .ok_or_else(|| std::io::Error::new(std::io::ErrorKind::InvalidData, "Invalid HTTP response"))
+    }
+}
+
+// a minimal HTTP/1.1 response parser, it expects the body to be delimited by the end of the
+// connection or by the Content-Length header
+fn parse_response(response: &[u8]) -> Option<(u16, Vec<u8>)> {
+    let header_end = response.windows(4).position(|window| window == b"\r\n\r\n")?;
+    let head = String::from_utf8_lossy(&response[..header_end]);
+    let mut lines = head.lines();
+    let status = lines.next()?.split_whitespace().nth(1)?.parse::<u16>().ok()?;
+    let mut body = response[header_end + 4..].to_vec();
+    for line in lines {
+        let (name, value) = line.split_once(':')?;
+        match name.trim().to_lowercase().as_str() {
+            "content-length" => body.truncate(value.trim().parse::<usize>().ok()?),
+            "transfer-encoding" if value.trim() != "identity" => return None,
+            _ => (),
+        }
+    }
+    Some((status, body))
+}
+
+impl Backend for HttpBackend {
+    fn get(&self, key: &str) -> Option<Vec<u8>> {
+        match self.request("GET", key, &[]) {
+            Ok((200, body)) => Some(body),
+            Ok(_) => None,
+            Err(e) => {
+                debug!("Could not read {} from the artifact cache: {}", key, e);
+                None
+            }
+        }
+    }
+
+    fn put(&self, key: &str, data: &[u8]) {
+        match self.request("PUT", key, data) {
+            Ok((status, _)) if (200..300).contains(&status) => (),
+            Ok((status, _)) => debug!("Could not write {} to the artifact cache: status {}", key, status),
+            Err(e) => debug!("Could not write {} to the artifact cache: {}", key, e),
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct ArtifactCache {
+    backend: Box<dyn Backend>,
+}
+
+impl ArtifactCache {
+    /// Creates a cache from a location, which is either an http:// url or a local directory
+    pub fn new(location: &str) -> Result<Self, String> {
+        if location.contains("://") {
+            Ok(Self::with_backend(Box::new(HttpBackend::new(location)?)))
+        } else {
+            fs::create_dir_all(location).map_err(|e| {
+                format!(
+                    "Could not create the artifact cache directory {}: {}",
+                    location, e
+                )
+            })?;
+            Ok(Self::with_backend(Box::new(LocalBackend {
+                path: PathBuf::from(location),
+            })))
+        }
+    }
+
+    pub fn with_backend(backend: Box<dyn Backend>) -> Self {
+        Self { backend }
+    }
+
+    /// Restores the outputs stored under the key, and returns the stderr of bsc when it produced
+    /// them. Returns None when the key is not in the cache.
+    pub fn restore(&self, key: &str, outputs: &[PathBuf]) -> Option<String> {
+        let (stderr, files) = decode(&self.backend.get(key)?)?;
+        if files.len() != outputs.len() {
+            return None;
+        }
+        for (output, contents) in outputs.iter().zip(files.iter()) {
+            match contents {
+                Some(contents) => {
+                    if let Some(parent) = output.parent() {
+                        let _ = fs::create_dir_all(parent);
+                    }
+                    fs::write(output, contents).ok()?;
+                }
+                None => {
+                    let _ = fs::remove_file(output);
+                }
+            }
+        }
+        debug!("Restored {} from the artifact cache", key);
+        Some(stderr)
+    }
+
+    /// Stores the outputs (that don't need to exist) and the stderr of bsc under the key
+    pub fn store(&self, key: &str, outputs: &[PathBuf], stderr: &str) {
+        let files = outputs
+            .iter()
+            .map(|output| fs::read(output).ok())
+            .collect::<Vec<_>>();
+        self.backend.put(key, &encode(stderr, &files));
+    }
+}
+
+/// Computes the key of an invocation of bsc from its arguments and the files it reads. The
+/// contents of the files are hashed, a missing file is hashed as such.
+pub fn get_key(version: &str, args: &[String], input_files: &[PathBuf], deps_cmi_hash: &str) -> String {
+    let mut hasher = blake3::Hasher::new();
+    let mut add = |bytes: &[u8]| {
+        hasher.update(&(bytes.len() as u64).to_le_bytes());
+        hasher.update(bytes);
+    };
+    add(CACHE_VERSION.as_bytes());
+    add(version.as_bytes());
+    add(&(args.len() as u64).to_le_bytes());
+    args.iter().for_each(|arg| add(arg.as_bytes()));
+    input_files
+        .iter()
+        .for_each(|input_file| match fs::read(input_file) {
+            Ok(contents) => add(blake3::hash(&contents).as_bytes()),
+            Err(_) => add(&[]),
+        });
+    add(deps_cmi_hash.as_bytes());
+    hasher.finalize().to_hex().to_string()
+}
+
+/// Computes the key of parsing a source file. The parser runs with `-absname`, so the absolute path
+/// of the source file ends up in the AST, and is part of the key as well. Otherwise a checkout in
+/// another folder would restore an AST that points at the files of this one.
+pub fn get_parse_key(version: &str, args: &[String], source_path: &Path) -> String {
+    let args = [args, &[source_path.to_string_lossy().to_string()]].concat();
+    get_key(version, &args, &[source_path.to_path_buf()], "")
+}
+
+/// The files that bsc reads when parsing or compiling a source file, besides the .cmi files of the
+/// dependencies. The implementation of a module with an interface is checked against the
+/// interface, so then the interface (and its AST) are read as well.
+pub fn get_input_files(
+    build_path: &str,
+    package_path: &str,
+    ast_path: &str,
+    source_path: &str,
+    interface_path: Option<&str>,
+) -> Vec<PathBuf> {
+    let mut input_files = vec![
+        Path::new(build_path).join(ast_path),
+        Path::new(package_path).join(source_path),
+    ];
+    if let Some(interface_path) = interface_path {
+        input_files.push(Path::new(build_path).join(helpers::get_ast_path(interface_path)));
+        input_files.push(Path::new(package_path).join(interface_path));
+    }
+    input_files
+}
+
+fn get_cmi_path(build_state: &BuildState, module: &Module) -> Option<PathBuf> {
+    let package = build_state.get_package(&module.package_name)?;
+    match &module.source_type {
+        SourceType::SourceFile(source_file) => Some(PathBuf::from(helpers::get_compiler_asset(
+            package,
+            &package.namespace,
+            &source_file.implementation.path,
+            "cmi",
+        ))),
+        SourceType::MlMap(_) => Some(PathBuf::from(package.get_mlmap_compile_path())),
+    }
+}
+
+/// Hashes the .cmi files of the dependencies of a module (and the namespace of its package), as
+/// they are the interfaces that bsc reads when compiling the module
+pub fn get_deps_cmi_hash(build_state: &BuildState, module: &Module) -> String {
+    let mut deps = module.deps.iter().collect::<Vec<&String>>();
+    deps.sort();
+    let mut cmi_paths = deps
+        .iter()
+        .filter_map(|dep| build_state.get_module(dep))
+        .filter_map(|dep_module| get_cmi_path(build_state, dep_module))
+        .collect::<Vec<PathBuf>>();
+    if let Some(package) = build_state.get_package(&module.package_name) {
+        if package.namespace.to_suffix().is_some() {
+            cmi_paths.push(PathBuf::from(package.get_mlmap_compile_path()));
+        }
+    }
+
+    let mut hasher = blake3::Hasher::new();
+    for cmi_path in cmi_paths {
+        hasher.update(cmi_path.to_string_lossy().as_bytes());
+        match helpers::compute_file_hash(Path::new(&cmi_path)) {
+            Some(hash) => hasher.update(hash.as_bytes()),
+            None => hasher.update(b"missing"),
+        };
+    }
+    hasher.finalize().to_hex().to_string()
+}
+
+// The artifacts are stored as: the stderr, the number of files, and for every file a flag if it
+// exists followed by its contents. Every string of bytes is prefixed with its length.
+fn encode(stderr: &str, files: &[Option<Vec<u8>>]) -> Vec<u8> {
+    let mut data = vec![];
+    let mut add = |bytes: &[u8]| {
+        data.extend((bytes.len() as u64).to_le_bytes());
+        data.extend(bytes);
+    };
+    add(stderr.as_bytes());
+    add(&(files.len() as u64).to_le_bytes());
+    for file in files {
+        match file {
+            Some(contents) => {
+                add(&[1]);
+                add(contents);
+            }
+            None => add(&[0]),
+        }
+    }
+    data
+}
+
+fn decode(data: &[u8]) -> Option<(String, Vec<Option<Vec<u8>>>)> {
+    let mut rest = data;
+    let mut next = || -> Option<&[u8]> {
+        // the data comes from the cache folder or server, so a corrupt length is a miss
+        let length = usize::try_from(u64::from_le_bytes(rest.get(..8)?.try_into().ok()?)).ok()?;
+        let end = length.checked_add(8)?;
+        let bytes = rest.get(8..end)?;
+        rest = &rest[end..];
+        Some(bytes)
+    };
+    let stderr = String::from_utf8(next()?.to_vec()).ok()?;
+    let count = u64::from_le_bytes(next()?.try_into().ok()?) as usize;
+    let mut files = vec![];
+    for _ in 0..count {
+        match next()? {
+            [1] => files.push(Some(next()?.to_vec())),
+            [0] => files.push(None),
+            _ => return None,
+        }
+    }
+    Some((stderr, files))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashMap;
+    use std::net::TcpListener;
+    use std::sync::{Arc, Mutex};
+
+    // a temp dir that is removed when the test is done
+    struct TempDir(PathBuf);
+
+    impl std::ops::Deref for TempDir {
+        type Target = PathBuf;
+
+        fn deref(&self) -> &PathBuf {
+            &self.0
+        }
+    }
+
+    impl Drop for TempDir {
+        fn drop(&mut self) {
+            let _ = fs::remove_dir_all(&self.0);
+        }
+    }
+
+    fn create_temp_dir(name: &str) -> TempDir {
+        let path = std::env::temp_dir().join(format!("rewatch-{}-{}", name, std::process::id()));
+        let _ = fs::remove_dir_all(&path);
+        fs::create_dir_all(&path).unwrap();
+        TempDir(path)
+    }
+
+    type Store = Arc<Mutex<HashMap<String, Vec<u8>>>>;
+
+    // a stand-in for a cache server, that keeps the artifacts in memory
+    fn start_server(requests: usize) -> (String, Store) {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let address = listener.local_addr().unwrap().to_string();
+        let store: Store = Arc::new(Mutex::new(HashMap::new()));
+        let server_store = store.clone();
+        std::thread::spawn(move || {
+            for stream in listener.incoming().take(requests) {
+                let mut stream = stream.unwrap();
+                let mut request = vec![];
+                let mut buffer = [0; 4096];
+                let (method, path, body) = loop {
+                    let read = stream.read(&mut buffer).unwrap();
+                    request.extend(&buffer[..read]);
+                    if let Some(header_end) = request.windows(4).position(|w| w == b"\r\n\r\n") {
+                        let head = String::from_utf8_lossy(&request[..header_end]).to_string();
+                        let mut request_line = head.lines().next().unwrap().split_whitespace();
+                        let method = request_line.next().unwrap().to_string();
+                        let path = request_line.next().unwrap().to_string();
+                        let length = head
+                            .lines()
+                            .find_map(|line| line.strip_prefix("Content-Length: "))
+                            .map(|length| length.parse::<usize>().unwrap())
+                            .unwrap_or(0);
+                        if request.len() >= header_end + 4 + length {
+                            break (method, path, request[header_end + 4..].to_vec());
+                        }
+                    }
+                };
+                let mut store = server_store.lock().unwrap();
+                let response = match (method.as_str(), store.get(&path)) {
+                    ("PUT", _) => {
+                        store.insert(path, body);
+                        b"HTTP/1.1 201 Created\r\nContent-Length: 0\r\n\r\n".to_vec()
+                    }
+                    ("GET", Some(contents)) => [
+                        format!("HTTP/1.1 200 OK\r\nContent-Length: {}\r\n\r\n", contents.len()).into_bytes(),
+                        contents.to_owned(),
+                    ]
+                    .concat(),
+                    _ => b"HTTP/1.1 404 Not Found\r\nContent-Length: 0\r\n\r\n".to_vec(),
+                };
+                stream.write_all(&response).unwrap();
+            }
+        });
+        (address, store)
+    }
+
+    #[test]
+    fn test_encode_decode() {
+        let files = vec![Some(b"cmi".to_vec()), None, Some(vec![])];
+        assert_eq!(
+            decode(&encode("warning", &files)),
+            Some(("warning".to_string(), files))
+        );
+        assert_eq!(decode(&encode("warning", &[])[..4]), None);
+        // a corrupt length
+        assert_eq!(decode(&u64::MAX.to_le_bytes()), None);
+        assert_eq!(decode(&[u64::MAX.to_le_bytes(), [0; 8]].concat()), None);
+    }
+
+    #[test]
+    fn test_get_key() {
+        let dir = create_temp_dir("artifact-cache-key");
+        let source = dir.join("Main.res");
+        fs::write(&source, "let a = 1").unwrap();
+        let args = vec!["-bs-ast".to_string()];
+        let key = get_key("11.1.0", &args, &[source.to_owned()], "");
+
+        assert_eq!(key, get_key("11.1.0", &args, &[source.to_owned()], ""));
+        assert_ne!(key, get_key("11.1.1", &args, &[source.to_owned()], ""));
+        assert_ne!(key, get_key("11.1.0", &[], &[source.to_owned()], ""));
+        assert_ne!(key, get_key("11.1.0", &args, &[source.to_owned()], "deps"));
+        fs::write(&source, "let a = 2").unwrap();
+        assert_ne!(key, get_key("11.1.0", &args, &[source.to_owned()], ""));
+    }
+
+    #[test]
+    fn test_get_parse_key() {
+        let dir = create_temp_dir("artifact-cache-parse");
+        let args = vec!["-bs-ast".to_string(), "../../src/Main.res".to_string()];
+        // two checkouts with the same source file
+        let sources = ["a", "b"].map(|checkout| {
+            let source = dir.join(checkout).join("src/Main.res");
+            fs::create_dir_all(source.parent().unwrap()).unwrap();
+            fs::write(&source, "let a = 1").unwrap();
+            source
+        });
+
+        assert_eq!(
+            get_parse_key("11.1.0", &args, &sources[0]),
+            get_parse_key("11.1.0", &args, &sources[0])
+        );
+        assert_ne!(
+            get_parse_key("11.1.0", &args, &sources[0]),
+            get_parse_key("11.1.0", &args, &sources[1])
+        );
+    }
+
+    #[test]
+    fn test_get_key_with_interface() {
+        let dir = create_temp_dir("artifact-cache-interface");
+        let build_path = dir.join("lib/bs").to_string_lossy().to_string();
+        let package_path = dir.to_string_lossy().to_string();
+        fs::create_dir_all(dir.join("lib/bs/src")).unwrap();
+        fs::create_dir_all(dir.join("src")).unwrap();
+        fs::write(dir.join("src/Main.res"), "let a = 1").unwrap();
+        fs::write(dir.join("src/Main.resi"), "let a: int").unwrap();
+        fs::write(dir.join("lib/bs/src/Main.ast"), "ast").unwrap();
+        fs::write(dir.join("lib/bs/src/Main.iast"), "iast").unwrap();
+        let input_files = get_input_files(
+            &build_path,
+            &package_path,
+            "src/Main.ast",
+            "src/Main.res",
+            Some("src/Main.resi"),
+        );
+        let key = get_key("11.1.0", &[], &input_files, "");
+
+        fs::write(dir.join("src/Main.resi"), "let a: string").unwrap();
+        fs::write(dir.join("lib/bs/src/Main.iast"), "changed iast").unwrap();
+        assert_ne!(key, get_key("11.1.0", &[], &input_files, ""));
+        assert_eq!(
+            get_input_files(&build_path, &package_path, "src/Main.ast", "src/Main.res", None),
+            vec![dir.join("lib/bs/src/Main.ast"), dir.join("src/Main.res")]
+        );
+    }
+
+    #[test]
+    fn test_local_backend() {
+        let dir = create_temp_dir("artifact-cache-local");
+        let cache = ArtifactCache::new(&dir.join("cache").to_string_lossy()).unwrap();
+        let outputs = vec![dir.join("lib/bs/src/Main.cmj"), dir.join("lib/bs/src/Main.cmi")];
+        fs::create_dir_all(dir.join("lib/bs/src")).unwrap();
+        fs::write(&outputs[0], "cmj").unwrap();
+
+        assert_eq!(cache.restore("key", &outputs), None);
+        cache.store("key", &outputs, "warning");
+        fs::write(&outputs[0], "changed").unwrap();
+        // an output that wasn't there when storing is removed
+        fs::write(&outputs[1], "cmi").unwrap();
+
+        assert_eq!(cache.restore("key", &outputs), Some("warning".to_string()));
+        assert_eq!(fs::read_to_string(&outputs[0]).unwrap(), "cmj");
+        assert!(!outputs[1].exists());
+    }
+
+    #[test]
+    fn test_http_backend() {
+        let (address, store) = start_server(3);
+        let cache = ArtifactCache::new(&format!("http://{}/cache/", address)).unwrap();
+        let dir = create_temp_dir("artifact-cache-http");
+        let outputs = vec![dir.join("Main.ast")];
+        fs::write(&outputs[0], "ast").unwrap();
+
+        assert_eq!(cache.restore("key", &outputs), None);
+        cache.store("key", &outputs, "");
+        assert!(store.lock().unwrap().contains_key("/cache/key"));
+        fs::remove_file(&outputs[0]).unwrap();
+
+        assert_eq!(cache.restore("key", &outputs), Some("".to_string()));
+        assert_eq!(fs::read_to_string(&outputs[0]).unwrap(), "ast");
+    }
+
+    #[test]
+    fn test_invalid_url() {
+        assert!(ArtifactCache::new("https://cache.example.com").is_err());
+        assert!(ArtifactCache::new("http:///cache").is_err());
+    }
+}
@@ -1,5 +1,9 @@
+use crate::build::artifact_cache::ArtifactCache;
+use crate::build::diagnostics::Diagnostic;
 use crate::build::packages::{Namespace, Package};
+use crate::build::profile::Profile;
 use ahash::{AHashMap, AHashSet};
+use serde::{Deserialize, Serialize};
 use std::{fmt::Display, time::SystemTime};
 
 #[derive(Debug, Clone, PartialEq)]
@@ -61,13 +65,46 @@ impl Display for SourceType {
     }
 }
 
+/// Why a module needs to be compiled (again)
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub enum DirtyReason {
+    NotCompiled,
+    SourceChanged,
+    ErrorsOrWarnings,
+    DeletedDependency,
+    // a module it depends on was compiled after it
+    ExpiredDependency,
+    DependencyInterfaceChanged(String),
+}
+
+impl Display for DirtyReason {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            DirtyReason::NotCompiled => write!(f, "it was not compiled yet"),
+            DirtyReason::SourceChanged => write!(f, "its source changed"),
+            DirtyReason::ErrorsOrWarnings => write!(f, "it had errors or warnings in the last build"),
+            DirtyReason::DeletedDependency => write!(f, "a module it depends on was deleted"),
+            DirtyReason::ExpiredDependency => write!(f, "a module it depends on was compiled after it"),
+            DirtyReason::DependencyInterfaceChanged(module_name) => write!(
+                f,
+                "the interface of {} changed",
+                crate::helpers::format_namespaced_module_name(module_name)
+            ),
+        }
+    }
+}
+
 #[derive(Debug, Clone)]
 pub struct Module {
     pub source_type: SourceType,
     pub deps: AHashSet<String>,
     pub dependents: AHashSet<String>,
     pub package_name: String,
+    // the module is in a source folder with `"type": "dev"`
+    pub is_type_dev: bool,
     pub compile_dirty: bool,
+    // why the module is compile_dirty, this is the first reason it was marked with
+    pub dirty_reason: Option<DirtyReason>,
     pub last_compiled_cmi: Option<SystemTime>,
     pub last_compiled_cmt: Option<SystemTime>,
     pub deps_dirty: bool,
@@ -78,6 +115,19 @@ impl Module {
         matches!(self.source_type, SourceType::MlMap(_))
     }
 
+    /// Marks the module to be compiled (again). When it already is, it keeps the first reason.
+    pub fn mark_compile_dirty(&mut self, reason: DirtyReason) {
+        self.compile_dirty = true;
+        if self.dirty_reason.is_none() {
+            self.dirty_reason = Some(reason);
+        }
+    }
+
+    pub fn mark_compile_clean(&mut self) {
+        self.compile_dirty = false;
+        self.dirty_reason = None;
+    }
+
     pub fn get_interface(&self) -> &Option<Interface> {
         match &self.source_type {
             SourceType::SourceFile(source_file) => &source_file.interface,
@@ -98,6 +148,16 @@ pub struct BuildState {
     pub bsc_path: String,
     pub workspace_root: Option<String>,
     pub deps_initialized: bool,
+    // the errors and warnings of bsc in the last (incremental) build
+    pub diagnostics: Vec<Diagnostic>,
+    // restores the outputs of bsc from earlier builds, when set
+    pub artifact_cache: Option<ArtifactCache>,
+    // the modules that were compiled in the last (incremental) build, with the reason
+    pub compiled_modules: Vec<(String, DirtyReason)>,
+    // only these modules and the modules they depend on are compiled, when set
+    pub entries: Option<Vec<String>>,
+    // records the spans of the build for --profile, when set
+    pub profile: Option<Profile>,
 }
 
 impl BuildState {
@@ -127,6 +187,11 @@ impl BuildState {
             rescript_version,
             bsc_path,
             deps_initialized: false,
+            diagnostics: vec![],
+            artifact_cache: None,
+            compiled_modules: vec![],
+            entries: None,
+            profile: None,
         }
     }
 
@@ -108,6 +108,7 @@ pub fn clean_mjs_files(build_state: &BuildState) {
 pub fn cleanup_previous_build(
     build_state: &mut BuildState,
     compile_assets_state: CompileAssetsState,
+    read_only: bool,
 ) -> (usize, usize) {
     // delete the .mjs file which appear in our previous compile assets
     // but does not exists anymore
@@ -140,10 +141,12 @@ pub fn cleanup_previous_build(
                 .packages
                 .get(package_name)
                 .expect("Could not find package");
-            remove_compile_assets(package, res_file_location);
-            remove_mjs_file(res_file_location, suffix);
-            remove_iast(package, res_file_location);
-            remove_ast(package, res_file_location);
+            if !read_only {
+                remove_compile_assets(package, res_file_location);
+                remove_mjs_file(res_file_location, suffix);
+                remove_iast(package, res_file_location);
+                remove_ast(package, res_file_location);
+            }
             match helpers::get_extension(ast_file_path).as_str() {
                 "iast" => Some(module_name.to_owned()),
                 "ast" => None,
@@ -183,7 +186,10 @@ pub fn cleanup_previous_build(
 
                 if let Some(last_modified) = last_modified {
                     if compile_dirty > last_modified && !deleted_interfaces.contains(module_name) {
-                        module.compile_dirty = false;
+                        module.mark_compile_clean();
+                    } else {
+                        // it was compiled before, from an older AST
+                        module.dirty_reason = Some(DirtyReason::SourceChanged);
                     }
                 }
             }
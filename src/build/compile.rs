@@ -1,8 +1,11 @@
 #![allow(clippy::too_many_arguments)]
 
-mod dependency_cycle;
+pub mod critical_path;
+pub mod dependency_cycle;
 
+use super::artifact_cache::{self, ArtifactCache};
 use super::build_types::*;
+use super::diagnostics;
 use super::logs;
 use super::packages;
 use crate::config;
@@ -12,9 +15,22 @@ use anyhow::anyhow;
 use console::style;
 use log::{debug, trace};
 use rayon::prelude::*;
-use std::path::Path;
+use std::cmp::Reverse;
+use std::collections::BinaryHeap;
+use std::path::{Path, PathBuf};
 use std::process::Command;
-use std::time::SystemTime;
+use std::sync::mpsc;
+use std::time::{Instant, SystemTime};
+
+// the name of the module, the result of compiling the implementation and the interface, if the
+// .cmi of the module stayed the same, and if the module was compiled
+type CompileResult = (
+    String,
+    Result<Option<String>, String>,
+    Option<Result<Option<String>, String>>,
+    bool,
+    bool,
+);
 
 pub fn compile(
     build_state: &mut BuildState,
@@ -23,7 +39,6 @@ pub fn compile(
     set_length: impl Fn(u64),
     build_dev_deps: bool,
 ) -> anyhow::Result<(String, String, usize)> {
-    let mut compiled_modules = AHashSet::<String>::new();
     let dirty_modules = build_state
         .modules
         .iter()
@@ -36,30 +51,15 @@ pub fn compile(
         })
         .collect::<AHashSet<String>>();
 
-    // dirty_modules.iter().for_each(|m| println!("dirty module: {}", m));
-    // println!("{} dirty modules", dirty_modules.len());
-    let mut sorted_dirty_modules = dirty_modules.iter().collect::<Vec<&String>>();
-    sorted_dirty_modules.sort();
-    // dirty_modules.iter().for_each(|m| println!("dirty module: {}", m));
-    // sorted_dirty_modules
-    //     .iter()
-    //     .for_each(|m| println!("dirty module: {}", m));
-
-    // for sure clean modules -- after checking the hash of the cmi
-    let mut clean_modules = AHashSet::<String>::new();
-
-    // TODO: calculate the real dirty modules from the original dirty modules in each iteration
-    // taken into account the modules that we know are clean, so they don't propagate through the
-    // deps graph
-    // create a hashset of all clean modules from the file-hashes
-    let mut loop_count = 0;
-    let mut files_total_count = compiled_modules.len();
-    let mut files_current_loop_count;
+    // the compile universe contains all modules that might need to be compiled. A module in there is
+    // only compiled when it's compile_dirty: when it was dirty from the start, or when one of its
+    // dependencies was compiled and its .cmi changed (early cutoff). If the .cmi of a module stays
+    // the same, its dependents don't need to be compiled again, and the change doesn't propagate
+    // further through the deps graph.
     let mut compile_errors = "".to_string();
     let mut compile_warnings = "".to_string();
+    let mut compile_diagnostics = vec![];
     let mut num_compiled_modules = 0;
-    let mut sorted_modules = build_state.module_names.iter().collect::<Vec<&String>>();
-    sorted_modules.sort();
 
     // this is the whole "compile universe" all modules that might be dirty
     // we get this by expanding the dependents from the dirty modules
@@ -86,267 +86,381 @@ pub fn compile(
     let compile_universe_count = compile_universe.len();
     set_length(compile_universe_count as u64);
 
-    // start off with all modules that have no deps in this compile universe
-    let mut in_progress_modules = compile_universe
-        .iter()
-        .filter(|module_name| {
-            let module = build_state.get_module(module_name).unwrap();
-            module.deps.intersection(&compile_universe).count() == 0
-        })
-        .map(|module_name| module_name.to_string())
-        .collect::<AHashSet<String>>();
+    let results = schedule(
+        build_state,
+        &compile_universe,
+        show_progress,
+        &inc,
+        build_dev_deps,
+    );
 
-    loop {
-        files_current_loop_count = 0;
-        loop_count += 1;
-
-        trace!(
-            "Compiled: {} out of {}. Compile loop: {}",
-            files_total_count,
-            compile_universe.len(),
-            loop_count,
-        );
+    for result in results.iter() {
+        let (module_name, result, interface_result, is_clean, is_compiled) = result;
 
-        let current_in_progres_modules = in_progress_modules.clone();
-
-        let results = current_in_progres_modules
-            .par_iter()
-            .filter_map(|module_name| {
-                let module = build_state.get_module(module_name).unwrap();
-                let package = build_state
-                    .get_package(&module.package_name)
-                    .expect("Package not found");
-                // all dependencies that we care about are compiled
-                if module
-                    .deps
-                    .intersection(&compile_universe)
-                    .all(|dep| compiled_modules.contains(dep))
-                {
-                    if !module.compile_dirty {
-                        // we are sure we don't have to compile this, so we can mark it as compiled and clean
-                        return Some((module_name.to_string(), Ok(None), Some(Ok(None)), true, false));
-                    }
-                    match module.source_type.to_owned() {
-                        SourceType::MlMap(_) => {
-                            // the mlmap needs to be compiled before the files are compiled
-                            // in the same namespace, otherwise we get a compile error
-                            // this is why mlmap is compiled in the AST generation stage
-                            // compile_mlmap(&module.package, module_name, &project_root);
-                            Some((
-                                package.namespace.to_suffix().unwrap(),
-                                Ok(None),
-                                Some(Ok(None)),
-                                false,
-                                false,
-                            ))
-                        }
-                        SourceType::SourceFile(source_file) => {
-                            let cmi_path = helpers::get_compiler_asset(
-                                package,
-                                &package.namespace,
-                                &source_file.implementation.path,
-                                "cmi",
-                            );
+        if *is_compiled {
+            num_compiled_modules += 1;
+            if let Some(dirty_reason) = &build_state.get_module(module_name).unwrap().dirty_reason {
+                build_state
+                    .compiled_modules
+                    .push((module_name.to_string(), dirty_reason.to_owned()));
+            }
+        }
 
-                            let cmi_digest = helpers::compute_file_hash(Path::new(&cmi_path));
-
-                            let package = build_state
-                                .get_package(&module.package_name)
-                                .expect("Package not found");
-
-                            let root_package =
-                                build_state.get_package(&build_state.root_config_name).unwrap();
-
-                            let interface_result = match source_file.interface.to_owned() {
-                                Some(Interface { path, .. }) => {
-                                    let result = compile_file(
-                                        package,
-                                        root_package,
-                                        &helpers::get_ast_path(&path).to_string_lossy(),
-                                        module,
-                                        &build_state.rescript_version,
-                                        true,
-                                        &build_state.bsc_path,
-                                        &build_state.packages,
-                                        &build_state.project_root,
-                                        &build_state.workspace_root,
-                                        build_dev_deps,
-                                    );
-                                    Some(result)
-                                }
-                                _ => None,
-                            };
-                            let result = compile_file(
-                                package,
-                                root_package,
-                                &helpers::get_ast_path(&source_file.implementation.path).to_string_lossy(),
-                                module,
-                                &build_state.rescript_version,
-                                false,
-                                &build_state.bsc_path,
-                                &build_state.packages,
-                                &build_state.project_root,
-                                &build_state.workspace_root,
-                                build_dev_deps,
-                            );
-                            let cmi_digest_after = helpers::compute_file_hash(Path::new(&cmi_path));
-
-                            // we want to compare both the hash of interface and the implementation
-                            // compile assets to verify that nothing changed. We also need to checke the interface
-                            // because we can include MyModule, so the modules that depend on this module might
-                            // change when this modules interface does not change, but the implementation does
-                            let is_clean_cmi = match (cmi_digest, cmi_digest_after) {
-                                (Some(cmi_digest), Some(cmi_digest_after)) => {
-                                    cmi_digest.eq(&cmi_digest_after)
-                                }
+        let module_dependents = build_state.get_module(module_name).unwrap().dependents.clone();
 
-                                _ => false,
-                            };
+        // if not clean -- compile modules that depend on this module
+        for dep in module_dependents.iter() {
+            //  mark the reverse dep as dirty when the source is not clean
+            if !*is_clean {
+                let dep_module = build_state.modules.get_mut(dep).unwrap();
+                //  mark the reverse dep as dirty when the source is not clean
+                dep_module
+                    .mark_compile_dirty(DirtyReason::DependencyInterfaceChanged(module_name.to_string()));
+            }
+        }
 
-                            Some((
-                                module_name.to_string(),
-                                result,
-                                interface_result,
-                                is_clean_cmi,
-                                true,
-                            ))
-                        }
+        let module = build_state
+            .modules
+            .get_mut(module_name)
+            .ok_or(anyhow!("Module not found"))?;
+
+        let package = build_state
+            .packages
+            .get(&module.package_name)
+            .ok_or(anyhow!("Package name not found"))?;
+
+        match module.source_type {
+            SourceType::MlMap(ref mut mlmap) => {
+                mlmap.parse_dirty = false;
+                module.mark_compile_clean();
+            }
+            SourceType::SourceFile(ref mut source_file) => {
+                match result {
+                    Ok(Some(err)) => {
+                        source_file.implementation.compile_state = CompileState::Warning;
+                        logs::append(package, err);
+                        compile_diagnostics.extend(diagnostics::parse(err, &package.name, module_name));
+                        compile_warnings.push_str(err);
                     }
-                } else {
-                    None
-                }
-                .inspect(|_res| {
-                    if show_progress {
-                        inc();
+                    Ok(None) => {
+                        source_file.implementation.compile_state = CompileState::Success;
+                    }
+                    Err(err) => {
+                        source_file.implementation.compile_state = CompileState::Error;
+                        logs::append(package, &err.to_string());
+                        compile_diagnostics.extend(diagnostics::parse(err, &package.name, module_name));
+                        compile_errors.push_str(&err.to_string());
+                    }
+                };
+                match interface_result {
+                    Some(Ok(Some(err))) => {
+                        source_file.interface.as_mut().unwrap().compile_state = CompileState::Warning;
+                        logs::append(package, &err.to_string());
+                        compile_diagnostics.extend(diagnostics::parse(err, &package.name, module_name));
+                        compile_warnings.push_str(&err.to_string());
+                    }
+                    Some(Ok(None)) => {
+                        if let Some(interface) = source_file.interface.as_mut() {
+                            interface.compile_state = CompileState::Success;
+                        }
                     }
-                })
-            })
-            .collect::<Vec<_>>();
-
-        for result in results.iter() {
-            let (module_name, result, interface_result, is_clean, is_compiled) = result;
-            in_progress_modules.remove(module_name);
 
-            if *is_compiled {
-                num_compiled_modules += 1;
+                    Some(Err(err)) => {
+                        source_file.interface.as_mut().unwrap().compile_state = CompileState::Error;
+                        logs::append(package, &err.to_string());
+                        compile_diagnostics.extend(diagnostics::parse(err, &package.name, module_name));
+                        compile_errors.push_str(&err.to_string());
+                    }
+                    _ => (),
+                };
+
+                match (result, interface_result) {
+                    // successfull compilation
+                    (Ok(None), Some(Ok(None))) | (Ok(None), None) => {
+                        module.mark_compile_clean();
+                        // the cmi only counts as compiled when it changed, so the dependents
+                        // are not seen as expired in the next build when it didn't
+                        if !*is_clean {
+                            module.last_compiled_cmi = Some(SystemTime::now());
+                        }
+                        module.last_compiled_cmt = Some(SystemTime::now());
+                    }
+                    // some error or warning
+                    (Err(_), _) | (_, Some(Err(_))) | (Ok(Some(_)), _) | (_, Some(Ok(Some(_)))) => {
+                        module.compile_dirty = true;
+                        module.dirty_reason = Some(DirtyReason::ErrorsOrWarnings);
+                    }
+                }
             }
+        }
+    }
 
-            files_current_loop_count += 1;
-            compiled_modules.insert(module_name.to_string());
+    if results.len() < compile_universe_count && compile_errors.is_empty() {
+        // find all dependency cycles, so they can be fixed in one go
+        let cycles = dependency_cycle::find_all(
+            &compile_universe
+                .iter()
+                .map(|s| (s, build_state.get_module(s).unwrap()))
+                .collect::<Vec<(&String, &Module)>>(),
+        );
 
-            if *is_clean {
-                // actually add it to a list of clean modules
-                clean_modules.insert(module_name.to_string());
-            }
+        let message = match cycles.len() {
+            0 => "Can't continue... Could not find the next modules to compile".to_string(),
+            1 => "Can't continue... Found a circular dependency in your code:".to_string(),
+            count => format!(
+                "Can't continue... Found {} circular dependencies in your code:",
+                count
+            ),
+        };
+        compile_errors.push_str(&format!(
+            "\n{}\n{}\n",
+            style(message).red(),
+            dependency_cycle::format_all(&cycles)
+        ))
+    }
 
-            let module_dependents = build_state.get_module(module_name).unwrap().dependents.clone();
+    build_state.diagnostics.extend(compile_diagnostics);
 
-            // if not clean -- compile modules that depend on this module
-            for dep in module_dependents.iter() {
-                //  mark the reverse dep as dirty when the source is not clean
-                if !*is_clean {
-                    let dep_module = build_state.modules.get_mut(dep).unwrap();
-                    //  mark the reverse dep as dirty when the source is not clean
-                    dep_module.compile_dirty = true;
-                }
-                if !compiled_modules.contains(dep) {
-                    in_progress_modules.insert(dep.to_string());
-                }
-            }
+    Ok((compile_errors, compile_warnings, num_compiled_modules))
+}
 
-            let module = build_state
-                .modules
-                .get_mut(module_name)
-                .ok_or(anyhow!("Module not found"))?;
+// Compiles the modules of the compile universe on the thread pool. A module starts as soon as the
+// last module it depends on is compiled, and of the modules that can start, the ones with the
+// longest chain of dependents go first. After an error, no new modules are started. The results
+// are in the order the modules were compiled in, and are applied to the build state afterwards,
+// because the build state is shared with the workers in the meantime.
+fn schedule(
+    build_state: &BuildState,
+    compile_universe: &AHashSet<String>,
+    show_progress: bool,
+    inc: &(impl Fn() + std::marker::Sync),
+    build_dev_deps: bool,
+) -> Vec<CompileResult> {
+    let critical_path_lengths = critical_path::get_lengths(
+        &compile_universe
+            .iter()
+            .map(|s| (s, build_state.get_module(s).unwrap()))
+            .collect::<Vec<(&String, &Module)>>(),
+    );
 
-            let package = build_state
-                .packages
-                .get(&module.package_name)
-                .ok_or(anyhow!("Package name not found"))?;
-
-            match module.source_type {
-                SourceType::MlMap(ref mut mlmap) => {
-                    module.compile_dirty = false;
-                    mlmap.parse_dirty = false;
+    // the number of modules in the compile universe each module is still waiting for
+    let mut num_pending_deps = compile_universe
+        .iter()
+        .map(|module_name| {
+            let module = build_state.get_module(module_name).unwrap();
+            (module_name, module.deps.intersection(compile_universe).count())
+        })
+        .collect::<AHashMap<&String, usize>>();
+
+    // start off with all modules that have no deps in this compile universe
+    let mut ready_modules = num_pending_deps
+        .iter()
+        .filter(|(_, num_pending)| **num_pending == 0)
+        .map(|(module_name, _)| (critical_path_lengths[*module_name], Reverse(*module_name)))
+        .collect::<BinaryHeap<(usize, Reverse<&String>)>>();
+
+    // the modules that become dirty because the .cmi of a module they depend on changed
+    let mut dirty_reasons: AHashMap<&String, DirtyReason> = AHashMap::new();
+    let mut results: Vec<CompileResult> = vec![];
+    let mut num_running = 0;
+    let mut has_errors = false;
+    let num_threads = rayon::current_num_threads();
+    let (sender, receiver) = mpsc::channel::<CompileResult>();
+
+    rayon::in_place_scope(|scope| loop {
+        while num_running < num_threads && !has_errors {
+            let Some((_, Reverse(module_name))) = ready_modules.pop() else {
+                break;
+            };
+            let module = build_state.get_module(module_name).unwrap();
+            let dirty_reason = if module.compile_dirty {
+                module.dirty_reason.to_owned()
+            } else {
+                dirty_reasons.get(module_name).cloned()
+            };
+            let compile_dirty = module.compile_dirty || dirty_reasons.contains_key(module_name);
+            let sender = sender.clone();
+            num_running += 1;
+            scope.spawn(move |_| {
+                let result = compile_module(
+                    build_state,
+                    module_name,
+                    compile_dirty,
+                    dirty_reason,
+                    build_dev_deps,
+                );
+                if show_progress {
+                    inc();
                 }
-                SourceType::SourceFile(ref mut source_file) => {
-                    match result {
-                        Ok(Some(err)) => {
-                            source_file.implementation.compile_state = CompileState::Warning;
-                            logs::append(package, err);
-                            compile_warnings.push_str(err);
-                        }
-                        Ok(None) => {
-                            source_file.implementation.compile_state = CompileState::Success;
-                        }
-                        Err(err) => {
-                            source_file.implementation.compile_state = CompileState::Error;
-                            logs::append(package, &err.to_string());
-                            compile_errors.push_str(&err.to_string());
-                        }
-                    };
-                    match interface_result {
-                        Some(Ok(Some(err))) => {
-                            source_file.interface.as_mut().unwrap().compile_state = CompileState::Warning;
-                            logs::append(package, &err.to_string());
-                            compile_warnings.push_str(&err.to_string());
-                        }
-                        Some(Ok(None)) => {
-                            if let Some(interface) = source_file.interface.as_mut() {
-                                interface.compile_state = CompileState::Success;
-                            }
-                        }
+                let _ = sender.send(result);
+            });
+        }
+        if num_running == 0 {
+            break;
+        }
 
-                        Some(Err(err)) => {
-                            source_file.interface.as_mut().unwrap().compile_state = CompileState::Error;
-                            logs::append(package, &err.to_string());
-                            compile_errors.push_str(&err.to_string());
-                        }
-                        _ => (),
-                    };
+        let result = receiver.recv().expect("A compile worker stopped");
+        num_running -= 1;
+        let (module_name, module_result, interface_result, is_clean, _) = &result;
+        if module_result.is_err() || matches!(interface_result, Some(Err(_))) {
+            has_errors = true;
+        }
 
-                    match (result, interface_result) {
-                        // successfull compilation
-                        (Ok(None), Some(Ok(None))) | (Ok(None), None) => {
-                            module.compile_dirty = false;
-                            module.last_compiled_cmi = Some(SystemTime::now());
-                            module.last_compiled_cmt = Some(SystemTime::now());
-                        }
-                        // some error or warning
-                        (Err(_), _) | (_, Some(Err(_))) | (Ok(Some(_)), _) | (_, Some(Ok(Some(_)))) => {
-                            module.compile_dirty = true;
-                        }
-                    }
-                }
+        let (module_name, module) = build_state.modules.get_key_value(module_name).unwrap();
+        for dependent in module.dependents.iter() {
+            let Some((dependent, num_pending)) = num_pending_deps.get_key_value(dependent) else {
+                continue;
+            };
+            if !*is_clean {
+                dirty_reasons
+                    .entry(dependent)
+                    .or_insert(DirtyReason::DependencyInterfaceChanged(module_name.to_string()));
+            }
+            let dependent = *dependent;
+            let num_pending = num_pending - 1;
+            num_pending_deps.insert(dependent, num_pending);
+            if num_pending == 0 {
+                ready_modules.push((critical_path_lengths[dependent], Reverse(dependent)));
             }
         }
+        results.push(result);
+
+        trace!("Compiled: {} out of {}", results.len(), compile_universe.len());
+    });
 
-        files_total_count += files_current_loop_count;
+    results
+}
 
-        if files_total_count == compile_universe_count {
-            break;
+fn compile_module(
+    build_state: &BuildState,
+    module_name: &str,
+    compile_dirty: bool,
+    dirty_reason: Option<DirtyReason>,
+    build_dev_deps: bool,
+) -> CompileResult {
+    let module = build_state.get_module(module_name).unwrap();
+    let package = build_state
+        .get_package(&module.package_name)
+        .expect("Package not found");
+    if !compile_dirty {
+        // we are sure we don't have to compile this, so we can mark it as compiled and clean
+        return (module_name.to_string(), Ok(None), Some(Ok(None)), true, false);
+    }
+    match module.source_type.to_owned() {
+        SourceType::MlMap(_) => {
+            // the mlmap needs to be compiled before the files are compiled
+            // in the same namespace, otherwise we get a compile error
+            // this is why mlmap is compiled in the AST generation stage
+            // compile_mlmap(&module.package, module_name, &project_root);
+            // the mlmap is clean when it didn't change when compiling it in the
+            // AST generation stage
+            (
+                package.namespace.to_suffix().unwrap(),
+                Ok(None),
+                Some(Ok(None)),
+                !module.compile_dirty,
+                false,
+            )
         }
-        if in_progress_modules.is_empty() || in_progress_modules.eq(&current_in_progres_modules) {
-            // find the dependency cycle
-            let cycle = dependency_cycle::find(
-                &compile_universe
-                    .iter()
-                    .map(|s| (s, build_state.get_module(s).unwrap()))
-                    .collect::<Vec<(&String, &Module)>>(),
+        SourceType::SourceFile(source_file) => {
+            match &dirty_reason {
+                Some(dirty_reason) => debug!(
+                    "Compiling {}: {}",
+                    helpers::format_namespaced_module_name(module_name),
+                    dirty_reason
+                ),
+                None => debug!(
+                    "Compiling {}",
+                    helpers::format_namespaced_module_name(module_name)
+                ),
+            }
+            let timing_compile = Instant::now();
+            let cmi_path = helpers::get_compiler_asset(
+                package,
+                &package.namespace,
+                &source_file.implementation.path,
+                "cmi",
             );
 
-            compile_errors.push_str(&format!(
-                "\n{}\n{}\n",
-                style("Can't continue... Found a circular dependency in your code:").red(),
-                dependency_cycle::format(&cycle)
-            ))
+            let cmi_digest = helpers::compute_file_hash(Path::new(&cmi_path));
+
+            let package = build_state
+                .get_package(&module.package_name)
+                .expect("Package not found");
+
+            let root_package = build_state.get_package(&build_state.root_config_name).unwrap();
+
+            // the dependencies are compiled, so their .cmi files are up to date
+            let artifact_cache = build_state
+                .artifact_cache
+                .as_ref()
+                .map(|cache| (cache, artifact_cache::get_deps_cmi_hash(build_state, module)));
+
+            let interface_result = match source_file.interface.to_owned() {
+                Some(Interface { path, .. }) => {
+                    let result = compile_file(
+                        package,
+                        root_package,
+                        &helpers::get_ast_path(&path).to_string_lossy(),
+                        module,
+                        &build_state.rescript_version,
+                        true,
+                        &build_state.bsc_path,
+                        &build_state.packages,
+                        &build_state.project_root,
+                        &build_state.workspace_root,
+                        build_dev_deps,
+                        &artifact_cache,
+                    );
+                    Some(result)
+                }
+                _ => None,
+            };
+            let result = compile_file(
+                package,
+                root_package,
+                &helpers::get_ast_path(&source_file.implementation.path).to_string_lossy(),
+                module,
+                &build_state.rescript_version,
+                false,
+                &build_state.bsc_path,
+                &build_state.packages,
+                &build_state.project_root,
+                &build_state.workspace_root,
+                build_dev_deps,
+                &artifact_cache,
+            )
+            .and_then(|warnings| {
+                run_js_post_build(package, root_package, &source_file.implementation.path).map(|_| warnings)
+            });
+            let cmi_digest_after = helpers::compute_file_hash(Path::new(&cmi_path));
+            if let Some(profile) = &build_state.profile {
+                profile.record(
+                    &helpers::format_namespaced_module_name(module_name),
+                    "compile",
+                    timing_compile,
+                    timing_compile.elapsed(),
+                );
+            }
+
+            // we want to compare both the hash of interface and the implementation
+            // compile assets to verify that nothing changed. We also need to checke the interface
+            // because we can include MyModule, so the modules that depend on this module might
+            // change when this modules interface does not change, but the implementation does
+            let is_clean_cmi = match (cmi_digest, cmi_digest_after) {
+                (Some(cmi_digest), Some(cmi_digest_after)) => cmi_digest.eq(&cmi_digest_after),
+
+                _ => false,
+            };
+            (
+                module_name.to_string(),
+                result,
+                interface_result,
+                is_clean_cmi,
+                true,
+            )
         }
-        if !compile_errors.is_empty() {
-            break;
-        };
     }
-
-    Ok((compile_errors, compile_warnings, num_compiled_modules))
 }
 
 pub fn compiler_args(
@@ -576,6 +690,52 @@ fn get_dependency_paths(
         .collect::<Vec<Vec<String>>>()
 }
 
+// the files that bsc writes when compiling a file, the compile assets in lib/bs and the js files
+fn get_compile_outputs(
+    package: &packages::Package,
+    root_package: &packages::Package,
+    implementation_file_path: &str,
+    module_name: &str,
+    is_interface: bool,
+    has_interface: bool,
+) -> Vec<PathBuf> {
+    let build_dir =
+        Path::new(&package.get_build_path()).join(Path::new(implementation_file_path).parent().unwrap());
+    let compile_asset = |extension: &str| build_dir.join(module_name.to_owned() + "." + extension);
+    if is_interface {
+        return vec![compile_asset("cmi"), compile_asset("cmti")];
+    }
+
+    let mut outputs = vec![compile_asset("cmj"), compile_asset("cmt")];
+    if !has_interface {
+        // with an interface, the .cmi is written when compiling the interface
+        outputs.push(compile_asset("cmi"));
+    }
+    outputs.extend(root_package.config.get_package_specs().iter().map(|spec| {
+        let js_file_path = if spec.in_source {
+            Path::new(&package.path).join(implementation_file_path)
+        } else {
+            Path::new(&package.path)
+                .join("lib")
+                .join(spec.get_out_of_source_dir())
+                .join(implementation_file_path)
+        };
+        helpers::get_source_file_from_rescript_file(&js_file_path, &root_package.config.get_suffix(spec))
+    }));
+    outputs
+}
+
+// we only copy the .cmi when it changed, so its modification time in lib/ocaml tells when the
+// interface of the module last changed (see mark_modules_with_expired_deps_dirty)
+fn copy_cmi(cmi_path: &Path, ocaml_build_path_abs: &str, module_name: &str) {
+    let destination = ocaml_build_path_abs.to_string() + "/" + module_name + ".cmi";
+    let destination = Path::new(&destination);
+    let cmi_hash = helpers::compute_file_hash(cmi_path);
+    if cmi_hash.is_none() || cmi_hash != helpers::compute_file_hash(destination) {
+        let _ = std::fs::copy(cmi_path, destination);
+    }
+}
+
 fn compile_file(
     package: &packages::Package,
     root_package: &packages::Package,
@@ -588,6 +748,7 @@ fn compile_file(
     project_root: &str,
     workspace_root: &Option<String>,
     build_dev_deps: bool,
+    artifact_cache: &Option<(&ArtifactCache, String)>,
 ) -> Result<Option<String>, String> {
     let ocaml_build_path_abs = package.get_ocaml_build_path();
     let build_path_abs = package.get_build_path();
@@ -614,38 +775,90 @@ fn compile_file(
         build_dev_deps,
     );
 
-    let to_mjs = Command::new(bsc_path)
-        .current_dir(helpers::canonicalize_string_path(&build_path_abs.to_owned()).unwrap())
-        .args(to_mjs_args)
-        .output();
-
-    match to_mjs {
-        Ok(x) if !x.status.success() => {
-            let stderr = String::from_utf8_lossy(&x.stderr);
-            let stdout = String::from_utf8_lossy(&x.stdout);
-            Err(stderr.to_string() + &stdout)
+    // gentype writes files that are not part of the outputs we know of, so we can't restore them
+    let artifact_cache = match artifact_cache {
+        Some((cache, deps_cmi_hash)) if package.config.get_gentype_arg().is_empty() => {
+            let interface_path = module
+                .get_interface()
+                .as_ref()
+                .map(|interface| interface.path.to_owned());
+            let (source_path, interface_path) = if is_interface {
+                (interface_path, None)
+            } else {
+                (Some(implementation_file_path.to_owned()), interface_path)
+            };
+            let key = artifact_cache::get_key(
+                version,
+                &to_mjs_args,
+                &artifact_cache::get_input_files(
+                    &build_path_abs,
+                    &package.path,
+                    ast_path,
+                    &source_path.unwrap_or_default(),
+                    interface_path.as_deref(),
+                ),
+                deps_cmi_hash,
+            );
+            let outputs = get_compile_outputs(
+                package,
+                root_package,
+                implementation_file_path,
+                &module_name,
+                is_interface,
+                has_interface,
+            );
+            Some((cache, key, outputs))
         }
-        Err(e) => Err(format!(
-            "Could not compile file. Error: {}. Path to AST: {:?}",
-            e, ast_path
-        )),
-        Ok(x) => {
-            let err = std::str::from_utf8(&x.stderr)
-                .expect("stdout should be non-null")
-                .to_string();
+        _ => None,
+    };
 
+    let to_mjs = match artifact_cache
+        .as_ref()
+        .and_then(|(cache, key, outputs)| cache.restore(key, outputs))
+    {
+        Some(stderr) => Ok(stderr),
+        None => match Command::new(bsc_path)
+            .current_dir(helpers::canonicalize_string_path(&build_path_abs.to_owned()).unwrap())
+            .args(to_mjs_args)
+            .output()
+        {
+            Ok(x) if !x.status.success() => {
+                let stderr = String::from_utf8_lossy(&x.stderr);
+                let stdout = String::from_utf8_lossy(&x.stdout);
+                Err(stderr.to_string() + &stdout)
+            }
+            Err(e) => Err(format!(
+                "Could not compile file. Error: {}. Path to AST: {:?}",
+                e, ast_path
+            )),
+            Ok(x) => {
+                let stderr = std::str::from_utf8(&x.stderr)
+                    .expect("stdout should be non-null")
+                    .to_string();
+                if let Some((cache, key, outputs)) = &artifact_cache {
+                    cache.store(key, outputs, &stderr);
+                }
+                Ok(stderr)
+            }
+        },
+    };
+
+    match to_mjs {
+        Err(e) => Err(e),
+        Ok(err) => {
             let dir = std::path::Path::new(implementation_file_path).parent().unwrap();
 
             // perhaps we can do this copying somewhere else
             if !is_interface {
-                let _ = std::fs::copy(
-                    std::path::Path::new(&package.get_build_path())
+                copy_cmi(
+                    &std::path::Path::new(&package.get_build_path())
                         .join(dir)
                         // because editor tooling doesn't support namespace entries yet
                         // we just remove the @ for now. This makes sure the editor support
                         // doesn't break
                         .join(module_name.to_owned() + ".cmi"),
-                    ocaml_build_path_abs.to_string() + "/" + &module_name + ".cmi",
+                    &ocaml_build_path_abs,
+                    &module_name,
                 );
                 let _ = std::fs::copy(
                     std::path::Path::new(&package.get_build_path())
@@ -669,11 +882,12 @@ fn compile_file(
                         .join(module_name.to_owned() + ".cmti"),
                     ocaml_build_path_abs.to_string() + "/" + &module_name + ".cmti",
                 );
-                let _ = std::fs::copy(
-                    std::path::Path::new(&package.get_build_path())
+                copy_cmi(
+                    &std::path::Path::new(&package.get_build_path())
                         .join(dir)
                         .join(module_name.to_owned() + ".cmi"),
-                    ocaml_build_path_abs.to_string() + "/" + &module_name + ".cmi",
+                    &ocaml_build_path_abs,
+                    &module_name,
                 );
             }
 
@@ -765,10 +979,78 @@ fn compile_file(
     }
 }
 
+// the `js-post-build` command of the package runs for every js file that is emitted for an
+// implementation (one per package-spec). It runs in the build folder of the package, and gets the
+// absolute path of the js file as the last argument.
+fn run_js_post_build(
+    package: &packages::Package,
+    root_package: &packages::Package,
+    implementation_path: &str,
+) -> Result<(), String> {
+    let cmd = match &package.config.js_post_build {
+        Some(js_post_build) => js_post_build.cmd.to_owned(),
+        None => return Ok(()),
+    };
+    let parsed_command = cmd.split_whitespace().collect::<Vec<&str>>();
+    let (command, params) = match parsed_command.split_first() {
+        Some(split) => split,
+        None => return Ok(()),
+    };
+
+    root_package
+        .config
+        .get_package_specs()
+        .iter()
+        .try_for_each(|spec| {
+            let output_dir = if spec.in_source {
+                Path::new(&package.path).to_path_buf()
+            } else {
+                Path::new(&package.path)
+                    .join("lib")
+                    .join(spec.get_out_of_source_dir())
+            };
+            let js_file = helpers::get_source_file_from_rescript_file(
+                &output_dir.join(implementation_path),
+                &root_package.config.get_suffix(spec),
+            );
+
+            debug!("Running js-post-build for: {}", js_file.to_string_lossy());
+            match Command::new(command)
+                .current_dir(package.get_build_path())
+                .args(params)
+                .arg(&js_file)
+                .output()
+            {
+                // when it fails, the output is part of the error, which is written to the
+                // .compiler.log with the errors of the module
+                Ok(x) if x.status.success() => {
+                    let stderr = String::from_utf8_lossy(&x.stderr);
+                    if !stderr.trim().is_empty() {
+                        logs::append(package, &stderr);
+                    }
+                    Ok(())
+                }
+                Ok(x) => Err(format!(
+                    "js-post-build \"{}\" failed for {}:\n{}{}",
+                    cmd,
+                    js_file.to_string_lossy(),
+                    String::from_utf8_lossy(&x.stderr),
+                    String::from_utf8_lossy(&x.stdout)
+                )),
+                Err(e) => Err(format!(
+                    "Could not run js-post-build \"{}\" for {}. Error: {}",
+                    cmd,
+                    js_file.to_string_lossy(),
+                    e
+                )),
+            }
+        })
+}
+
 pub fn mark_modules_with_deleted_deps_dirty(build_state: &mut BuildState) {
     build_state.modules.iter_mut().for_each(|(_, module)| {
         if !module.deps.is_disjoint(&build_state.deleted_modules) {
-            module.compile_dirty = true;
+            module.mark_compile_dirty(DirtyReason::DeletedDependency);
         }
     });
 }
@@ -798,7 +1080,7 @@ pub fn mark_modules_with_expired_deps_dirty(build_state: &mut BuildState) {
                 let dependent_module = build_state.modules.get(dependent).unwrap();
                 match dependent_module.source_type {
                     SourceType::SourceFile(_) => {
-                        match (module.last_compiled_cmt, module.last_compiled_cmt) {
+                        match (module.last_compiled_cmi, module.last_compiled_cmt) {
                             (None, None) | (Some(_), None) | (None, Some(_)) => {
                                 // println!(
                                 //     "🛑 {} is a dependent of {} but has no cmt/cmi",
@@ -812,7 +1094,7 @@ pub fn mark_modules_with_expired_deps_dirty(build_state: &mut BuildState) {
                         // we compare the last compiled time of the dependent module with the last
                         // compile of the interface of the module it depends on, if the interface
                         // didn't change it doesn't matter
-                        match (dependent_module.last_compiled_cmt, module.last_compiled_cmt) {
+                        match (dependent_module.last_compiled_cmt, module.last_compiled_cmi) {
                             (Some(last_compiled_dependent), Some(last_compiled)) => {
                                 if last_compiled_dependent < last_compiled {
                                     // println!(
@@ -845,7 +1127,7 @@ pub fn mark_modules_with_expired_deps_dirty(build_state: &mut BuildState) {
                             let dependent_module = build_state.modules.get(dependent_of_namespace).unwrap();
 
                             if let (Some(last_compiled_dependent), Some(last_compiled)) =
-                                (dependent_module.last_compiled_cmt, module.last_compiled_cmt)
+                                (dependent_module.last_compiled_cmt, module.last_compiled_cmi)
                             {
                                 if last_compiled_dependent < last_compiled {
                                     modules_with_expired_deps.insert(dependent.to_string());
@@ -858,7 +1140,7 @@ pub fn mark_modules_with_expired_deps_dirty(build_state: &mut BuildState) {
         });
     build_state.modules.iter_mut().for_each(|(module_name, module)| {
         if modules_with_expired_deps.contains(module_name) {
-            module.compile_dirty = true;
+            module.mark_compile_dirty(DirtyReason::ExpiredDependency);
         }
     });
 }
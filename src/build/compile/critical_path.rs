@@ -0,0 +1,104 @@
+use super::super::build_types::*;
+use ahash::{AHashMap, AHashSet};
+
+fn get_length<'a>(
+    module_name: &'a String,
+    modules: &AHashMap<&'a String, &'a Module>,
+    lengths: &mut AHashMap<&'a String, usize>,
+    visiting: &mut AHashSet<&'a String>,
+) -> usize {
+    if let Some(length) = lengths.get(module_name) {
+        return *length;
+    }
+    // a dependency cycle, these modules are never compiled
+    if !visiting.insert(module_name) {
+        return 0;
+    }
+    let length = 1 + modules[module_name]
+        .dependents
+        .iter()
+        .filter_map(|dependent| modules.get_key_value(dependent))
+        .map(|(dependent, _)| get_length(dependent, modules, lengths, visiting))
+        .max()
+        .unwrap_or(0);
+    visiting.remove(module_name);
+    lengths.insert(module_name, length);
+    length
+}
+
+/// The number of modules in the longest chain of dependents that starts at each module (including
+/// the module itself), only counting the given modules. The modules with the longest chain are on
+/// the critical path of the build, and are compiled first.
+pub fn get_lengths(modules: &[(&String, &Module)]) -> AHashMap<String, usize> {
+    let module_map = modules
+        .iter()
+        .map(|(name, module)| (*name, *module))
+        .collect::<AHashMap<&String, &Module>>();
+    let mut lengths = AHashMap::new();
+    let mut visiting = AHashSet::new();
+    for (module_name, _) in modules {
+        get_length(module_name, &module_map, &mut lengths, &mut visiting);
+    }
+    lengths
+        .into_iter()
+        .map(|(module_name, length)| (module_name.to_owned(), length))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn create_module(dependents: &[&str]) -> Module {
+        Module {
+            source_type: SourceType::MlMap(MlMap { parse_dirty: false }),
+            deps: AHashSet::new(),
+            dependents: dependents.iter().map(|dependent| dependent.to_string()).collect(),
+            package_name: "main".to_string(),
+            is_type_dev: false,
+            compile_dirty: false,
+            dirty_reason: None,
+            last_compiled_cmi: None,
+            last_compiled_cmt: None,
+            deps_dirty: false,
+        }
+    }
+
+    #[test]
+    fn test_get_lengths() {
+        // Utils <- Button <- App, Utils <- Theme, and App depends on Other, which is not compiled
+        let names = ["Utils", "Button", "Theme", "App"].map(|name| name.to_string());
+        let modules = [
+            create_module(&["Button", "Theme"]),
+            create_module(&["App"]),
+            create_module(&[]),
+            create_module(&["Other"]),
+        ];
+        let lengths = get_lengths(
+            &names
+                .iter()
+                .zip(modules.iter())
+                .collect::<Vec<(&String, &Module)>>(),
+        );
+
+        assert_eq!(lengths["Utils"], 3);
+        assert_eq!(lengths["Button"], 2);
+        assert_eq!(lengths["Theme"], 1);
+        assert_eq!(lengths["App"], 1);
+    }
+
+    #[test]
+    fn test_get_lengths_with_cycle() {
+        let names = ["A", "B"].map(|name| name.to_string());
+        let modules = [create_module(&["B"]), create_module(&["A"])];
+        let lengths = get_lengths(
+            &names
+                .iter()
+                .zip(modules.iter())
+                .collect::<Vec<(&String, &Module)>>(),
+        );
+
+        assert_eq!(lengths["A"], 2);
+        assert_eq!(lengths["B"], 1);
+    }
+}
@@ -1,11 +1,7 @@
 use super::super::build_types::*;
 use crate::helpers;
 use ahash::AHashSet;
-use std::collections::{HashMap, HashSet, VecDeque};
-
-pub fn find(modules: &Vec<(&String, &Module)>) -> Vec<String> {
-    find_shortest_cycle(modules)
-}
+use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet, VecDeque};
 
 fn find_shortest_cycle(modules: &Vec<(&String, &Module)>) -> Vec<String> {
     let mut shortest_cycle: Vec<String> = Vec::new();
@@ -85,12 +81,40 @@ fn find_shortest_cycle(modules: &Vec<(&String, &Module)>) -> Vec<String> {
     shortest_cycle
 }
 
+/// Finds the shortest chain of dependencies from one module to another, the chain starts with
+/// `from` and ends with `to`
+pub fn find_path(modules: &Vec<(&String, &Module)>, from: &String, to: &String) -> Option<Vec<String>> {
+    if from == to {
+        return Some(vec![from.to_owned()]);
+    }
+    let graph = modules
+        .iter()
+        .map(|(name, module)| (*name, &module.deps))
+        .collect::<HashMap<&String, &AHashSet<String>>>();
+
+    find_path_bfs(from, to, &graph, usize::MAX).map(|mut path| {
+        path.push(from.to_owned());
+        path.reverse();
+        path
+    })
+}
+
 fn find_cycle_bfs(
     start: &String,
     graph: &HashMap<&String, &AHashSet<String>>,
     max_length: usize,
 ) -> Option<Vec<String>> {
-    // Use a BFS to find the shortest cycle
+    find_path_bfs(start, start, graph, max_length)
+}
+
+// returns the path in reverse, from the target back to the node after the start
+fn find_path_bfs(
+    start: &String,
+    target: &String,
+    graph: &HashMap<&String, &AHashSet<String>>,
+    max_length: usize,
+) -> Option<Vec<String>> {
+    // Use a BFS to find the shortest path (or cycle when the target is the start)
     let mut queue = VecDeque::new();
     // Store node -> (distance, parent)
     let mut visited: HashMap<String, (usize, Option<String>)> = HashMap::new();
@@ -111,11 +135,11 @@ fn find_cycle_bfs(
         // Check all neighbors
         if let Some(neighbors) = graph.get(&current) {
             for neighbor in neighbors.iter() {
-                // If we found the start node again, we have a cycle
-                if neighbor == start {
-                    // Reconstruct the cycle
+                // If we found the target (or the start node again), we have a path (or a cycle)
+                if neighbor == target {
+                    // Reconstruct the path
                     let mut path = Vec::new();
-                    path.push(start.clone());
+                    path.push(target.clone());
 
                     // Backtrack from current to start using parent pointers
                     let mut curr = current.clone();
@@ -139,15 +163,256 @@ fn find_cycle_bfs(
     None
 }
 
-pub fn format(cycle: &[String]) -> String {
-    let mut cycle = cycle.to_vec();
-    cycle.reverse();
-    // add the first module to the end of the cycle
-    cycle.push(cycle[0].clone());
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Cycle {
+    // the modules of the cycle, every module depends on the next one (and the last on the first)
+    pub modules: Vec<String>,
+    // the other modules that depend on each other together with the modules of the cycle
+    pub other_modules: Vec<String>,
+    // the packages of all these modules
+    pub packages: Vec<String>,
+}
 
-    cycle
+/// Finds all groups of modules that depend on each other (the strongly connected components of
+/// the dependency graph), with the shortest cycle of each group
+pub fn find_all(modules: &Vec<(&String, &Module)>) -> Vec<Cycle> {
+    let module_map = modules
+        .iter()
+        .map(|(name, module)| (*name, *module))
+        .collect::<HashMap<&String, &Module>>();
+
+    let mut cycles = find_strongly_connected_components(&module_map)
+        .into_iter()
+        .filter(|component| component.len() > 1)
+        .map(|component| {
+            let component_modules = component
+                .iter()
+                .map(|name| (*name, module_map[name]))
+                .collect::<Vec<(&String, &Module)>>();
+            let mut cycle = find_shortest_cycle(&component_modules);
+            // the cycle is found in reverse, and we start it at the first module by name to
+            // have the same output in every build
+            cycle.reverse();
+            let first = (0..cycle.len()).min_by_key(|index| &cycle[*index]).unwrap_or(0);
+            cycle.rotate_left(first);
+
+            let other_modules = component
+                .iter()
+                .filter(|name| !cycle.contains(name))
+                .map(|name| name.to_string())
+                .collect::<BTreeSet<String>>();
+            let packages = component
+                .iter()
+                .map(|name| module_map[name].package_name.to_owned())
+                .collect::<BTreeSet<String>>();
+            Cycle {
+                modules: cycle,
+                other_modules: other_modules.into_iter().collect(),
+                packages: packages.into_iter().collect(),
+            }
+        })
+        .collect::<Vec<Cycle>>();
+
+    cycles.sort_by(|a, b| (&a.packages, &a.modules).cmp(&(&b.packages, &b.modules)));
+    cycles
+}
+
+// Tarjan's algorithm, without recursion so large graphs don't overflow the stack
+fn find_strongly_connected_components<'a>(
+    module_map: &HashMap<&'a String, &'a Module>,
+) -> Vec<Vec<&'a String>> {
+    let mut names = module_map.keys().cloned().collect::<Vec<&String>>();
+    names.sort();
+    let graph = names
+        .iter()
+        .map(|name| {
+            let mut deps = module_map[name]
+                .deps
+                .iter()
+                .filter_map(|dep| module_map.get_key_value(dep).map(|(dep, _)| *dep))
+                .collect::<Vec<&String>>();
+            deps.sort();
+            (*name, deps)
+        })
+        .collect::<HashMap<&String, Vec<&String>>>();
+
+    let mut next_index = 0;
+    let mut indices: HashMap<&String, usize> = HashMap::new();
+    let mut low_links: HashMap<&String, usize> = HashMap::new();
+    let mut stack: Vec<&String> = vec![];
+    let mut on_stack: HashSet<&String> = HashSet::new();
+    let mut components = vec![];
+
+    for root in names {
+        if indices.contains_key(root) {
+            continue;
+        }
+        // the nodes we are visiting, with the index of the next dependency to visit
+        let mut call_stack: Vec<(&String, usize)> = vec![(root, 0)];
+        indices.insert(root, next_index);
+        low_links.insert(root, next_index);
+        next_index += 1;
+        stack.push(root);
+        on_stack.insert(root);
+
+        while let Some((node, next_dep)) = call_stack.last_mut() {
+            let node = *node;
+            match graph[node].get(*next_dep) {
+                Some(dep) => {
+                    *next_dep += 1;
+                    if !indices.contains_key(dep) {
+                        indices.insert(dep, next_index);
+                        low_links.insert(dep, next_index);
+                        next_index += 1;
+                        stack.push(dep);
+                        on_stack.insert(dep);
+                        call_stack.push((dep, 0));
+                    } else if on_stack.contains(dep) {
+                        let low_link = low_links[node].min(indices[dep]);
+                        low_links.insert(node, low_link);
+                    }
+                }
+                None => {
+                    call_stack.pop();
+                    if let Some((parent, _)) = call_stack.last() {
+                        let low_link = low_links[parent].min(low_links[node]);
+                        low_links.insert(parent, low_link);
+                    }
+                    if low_links[node] == indices[node] {
+                        let mut component = vec![];
+                        while let Some(member) = stack.pop() {
+                            on_stack.remove(member);
+                            component.push(member);
+                            if member == node {
+                                break;
+                            }
+                        }
+                        components.push(component);
+                    }
+                }
+            }
+        }
+    }
+
+    components
+}
+
+fn format_cycle(cycle: &Cycle) -> String {
+    let mut modules = cycle.modules.to_vec();
+    // add the first module to the end of the cycle
+    modules.push(modules[0].clone());
+    let mut formatted = modules
         .iter()
         .map(|s| helpers::format_namespaced_module_name(s))
         .collect::<Vec<String>>()
-        .join("\n → ")
+        .join("\n → ");
+
+    if !cycle.other_modules.is_empty() {
+        formatted.push_str(&format!(
+            "\nOther modules that are part of this cycle: {}",
+            cycle
+                .other_modules
+                .iter()
+                .map(|s| helpers::format_namespaced_module_name(s))
+                .collect::<Vec<String>>()
+                .join(", ")
+        ));
+    }
+    formatted
+}
+
+/// Formats all cycles, grouped by the packages they are in
+pub fn format_all(cycles: &[Cycle]) -> String {
+    let mut groups: BTreeMap<&Vec<String>, Vec<&Cycle>> = BTreeMap::new();
+    for cycle in cycles {
+        groups.entry(&cycle.packages).or_default().push(cycle);
+    }
+
+    groups
+        .iter()
+        .map(|(packages, cycles)| {
+            format!(
+                "In {} {}:\n{}",
+                if packages.len() == 1 {
+                    "package"
+                } else {
+                    "packages"
+                },
+                packages.join(", "),
+                cycles
+                    .iter()
+                    .map(|cycle| format_cycle(cycle))
+                    .collect::<Vec<String>>()
+                    .join("\n\n")
+            )
+        })
+        .collect::<Vec<String>>()
+        .join("\n\n")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn create_module(package_name: &str, deps: &[&str]) -> Module {
+        Module {
+            source_type: SourceType::MlMap(MlMap { parse_dirty: false }),
+            deps: deps.iter().map(|dep| dep.to_string()).collect(),
+            dependents: AHashSet::new(),
+            package_name: package_name.to_string(),
+            is_type_dev: false,
+            compile_dirty: false,
+            dirty_reason: None,
+            last_compiled_cmi: None,
+            last_compiled_cmt: None,
+            deps_dirty: false,
+        }
+    }
+
+    #[test]
+    fn test_find_all() {
+        let modules = [
+            ("A", create_module("main", &["B"])),
+            ("B", create_module("main", &["C", "A"])),
+            ("C", create_module("main", &["D"])),
+            ("D", create_module("main", &["B"])),
+            ("E", create_module("main", &["A"])),
+            ("Button-Ui", create_module("ui", &["Icon-Ui", "Theme"])),
+            ("Icon-Ui", create_module("ui", &["Button-Ui"])),
+            ("Theme", create_module("theme", &["Icon-Ui"])),
+        ]
+        .iter()
+        .map(|(name, module)| (name.to_string(), module.to_owned()))
+        .collect::<Vec<(String, Module)>>();
+        let cycles = find_all(&modules.iter().map(|(name, module)| (name, module)).collect());
+
+        assert_eq!(
+            cycles,
+            vec![
+                Cycle {
+                    modules: vec!["A".to_string(), "B".to_string()],
+                    other_modules: vec!["C".to_string(), "D".to_string()],
+                    packages: vec!["main".to_string()],
+                },
+                Cycle {
+                    modules: vec!["Button-Ui".to_string(), "Icon-Ui".to_string()],
+                    other_modules: vec!["Theme".to_string()],
+                    packages: vec!["theme".to_string(), "ui".to_string()],
+                },
+            ]
+        );
+        assert_eq!(
+            format_all(&cycles),
+            [
+                "In package main:",
+                "A\n → B\n → A",
+                "Other modules that are part of this cycle: C, D",
+                "",
+                "In packages theme, ui:",
+                "Ui.Button\n → Ui.Icon\n → Ui.Button",
+                "Other modules that are part of this cycle: Theme",
+            ]
+            .join("\n")
+        );
+    }
 }
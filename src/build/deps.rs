@@ -1,17 +1,72 @@
 use super::build_types::*;
+use super::logs;
 use super::packages;
 use crate::helpers;
 use ahash::AHashSet;
+use console::style;
 use rayon::prelude::*;
 
-fn get_dep_modules(
-    ast_file: &str,
-    namespace: Option<String>,
-    package_modules: &AHashSet<String>,
-    valid_modules: &AHashSet<String>,
+// a dependency in the AST can be a module of a package without a namespace (`Module`), or a
+// module inside of the namespace of another package (`Namespace.Module`). In the latter case the
+// module is called `Module-Namespace`
+fn get_referenced_module_name(dep: &str, build_state: &BuildState) -> String {
+    let dep_first = dep.split('.').next().unwrap();
+    match dep.split('.').nth(1) {
+        // the namespace of a package with a namespace entry is prefixed with @
+        Some(dep_second)
+            if build_state.packages.values().any(|package| {
+                package
+                    .namespace
+                    .to_suffix()
+                    .is_some_and(|suffix| suffix == dep_first)
+            }) =>
+        {
+            dep_second.to_string() + "-" + dep_first
+        }
+        _ => dep_first.to_string(),
+    }
+}
+
+// a module in the own namespace can have the same name as a module of another package
+fn is_own_namespace_module(dep: &str, package: &packages::Package) -> bool {
+    match package.namespace.to_suffix() {
+        Some(namespace) => {
+            let dep_first = dep.split('.').next().unwrap();
+            dep_first == namespace
+                || package
+                    .modules
+                    .as_ref()
+                    .is_some_and(|modules| modules.contains(&(dep_first.to_string() + "-" + &namespace)))
+        }
+        None => false,
+    }
+}
+
+// returns the modules of other packages that this module uses, but are not public in their package
+fn get_private_dep_modules(
+    deps: &AHashSet<String>,
+    allowed_dependencies: &AHashSet<String>,
     package: &packages::Package,
     build_state: &BuildState,
 ) -> AHashSet<String> {
+    deps.iter()
+        .filter(|dep| !is_own_namespace_module(dep, package))
+        .map(|dep| get_referenced_module_name(dep, build_state))
+        .filter(|dep| match build_state.modules.get(dep) {
+            Some(dep_module) if dep_module.package_name != package.name => {
+                allowed_dependencies.contains(&dep_module.package_name)
+                    && build_state
+                        .get_package(&dep_module.package_name)
+                        .map(|dep_package| dep_package.private_modules.contains(dep))
+                        .unwrap_or(false)
+            }
+            _ => false,
+        })
+        .collect::<AHashSet<String>>()
+}
+
+// the modules that are referenced in the AST, as they are written in the source
+fn read_ast_deps(ast_file: &str, package: &packages::Package) -> AHashSet<String> {
     let mut deps = AHashSet::new();
     let ast_file = package.get_build_path() + "/" + ast_file;
     if let Ok(lines) = helpers::read_lines(ast_file.to_string()) {
@@ -30,6 +85,18 @@ fn get_dep_modules(
     } else {
         panic!("Could not read file {}", ast_file);
     }
+    deps
+}
+
+fn get_dep_modules(
+    ast_file: &str,
+    namespace: Option<String>,
+    package_modules: &AHashSet<String>,
+    valid_modules: &AHashSet<String>,
+    package: &packages::Package,
+    build_state: &BuildState,
+) -> (AHashSet<String>, AHashSet<String>) {
+    let deps = read_ast_deps(ast_file, package);
 
     // Get the list of allowed dependency packages for this package
     let allowed_dependencies: AHashSet<String> = package
@@ -49,7 +116,9 @@ fn get_dep_modules(
         .cloned()
         .collect();
 
-    return deps
+    let private_deps = get_private_dep_modules(&deps, &allowed_dependencies, package, build_state);
+
+    let deps = deps
         .iter()
         .map(|dep| {
             let dep_first = dep.split('.').next().unwrap();
@@ -99,22 +168,224 @@ fn get_dep_modules(
             true
         })
         .collect::<AHashSet<String>>();
+
+    (deps, private_deps)
+}
+
+fn format_private_dep_error(
+    module_name: &str,
+    private_dep: &str,
+    package: &packages::Package,
+    build_state: &BuildState,
+) -> String {
+    let dep_package_name = build_state
+        .get_module(private_dep)
+        .map(|module| module.package_name.to_owned())
+        .unwrap_or_default();
+    format!(
+        "\n{}: {} (in {}) uses {}, which is not public in {}.\nAdd it to the \"public\" modules of its source folder in the config of {}\n",
+        style("Error").red(),
+        style(helpers::format_namespaced_module_name(module_name)).bold(),
+        package.name,
+        style(helpers::format_namespaced_module_name(private_dep)).bold(),
+        dep_package_name,
+        dep_package_name
+    )
+}
+
+// returns the modules that a module outside of the dev source folders uses, but are only part of the
+// build for development: modules in a dev source folder, or modules of a package that is only one
+// of the bs-dev-dependencies
+fn get_dev_dep_modules(
+    module: &Module,
+    deps: &AHashSet<String>,
+    package: &packages::Package,
+    build_state: &BuildState,
+) -> AHashSet<String> {
+    if module.is_type_dev {
+        return AHashSet::new();
+    }
+    let is_dependency = |package_name: &String| {
+        package
+            .config
+            .bs_dependencies
+            .as_ref()
+            .is_some_and(|dependencies| dependencies.contains(package_name))
+    };
+    let is_dev_dependency = |package_name: &String| {
+        package
+            .config
+            .bs_dev_dependencies
+            .as_ref()
+            .is_some_and(|dependencies| dependencies.contains(package_name))
+    };
+    deps.iter()
+        .filter(|dep| match build_state.get_module(dep) {
+            Some(dep_module) => {
+                dep_module.is_type_dev
+                    || (dep_module.package_name != package.name
+                        && is_dev_dependency(&dep_module.package_name)
+                        && !is_dependency(&dep_module.package_name))
+            }
+            None => false,
+        })
+        .cloned()
+        .collect()
+}
+
+fn format_dev_dep_error(
+    module_name: &str,
+    dev_dep: &str,
+    package: &packages::Package,
+    build_state: &BuildState,
+) -> String {
+    let dep_module = build_state.get_module(dev_dep);
+    let dep_package_name = dep_module
+        .map(|module| module.package_name.to_owned())
+        .unwrap_or_default();
+    let reason = if dep_module.is_some_and(|module| module.is_type_dev) {
+        format!("which is in a dev source folder of {}", dep_package_name)
+    } else {
+        format!(
+            "a module of {}, which is only in the bs-dev-dependencies of {}",
+            dep_package_name, package.name
+        )
+    };
+    format!(
+        "\n{}: {} (in {}) uses {}, {}.\nOnly modules in a dev source folder (\"type\": \"dev\") can use it\n",
+        style("Error").red(),
+        style(helpers::format_namespaced_module_name(module_name)).bold(),
+        package.name,
+        style(helpers::format_namespaced_module_name(dev_dep)).bold(),
+        reason
+    )
+}
+
+// the ASTs are missing when a module was not parsed yet, which happens in a dry run
+fn has_asts(source_file: &SourceFile, package: &packages::Package) -> bool {
+    std::iter::once(&source_file.implementation.path)
+        .chain(source_file.interface.as_ref().map(|interface| &interface.path))
+        .all(|path| {
+            std::path::Path::new(&package.get_build_path())
+                .join(helpers::get_ast_path(path))
+                .exists()
+        })
 }
 
-pub fn get_deps(build_state: &mut BuildState, deleted_modules: &AHashSet<String>) {
+/// Reads the dependencies of a module again, separately from the AST of its implementation (.ast)
+/// and its interface (.iast), to explain where a dependency comes from
+pub fn get_implementation_and_interface_deps(
+    build_state: &BuildState,
+    module_name: &str,
+) -> Option<(AHashSet<String>, AHashSet<String>)> {
+    let module = build_state.get_module(module_name)?;
+    let source_file = match &module.source_type {
+        SourceType::SourceFile(source_file) => source_file,
+        SourceType::MlMap(_) => return None,
+    };
+    let package = build_state.get_package(&module.package_name)?;
+    let all_mod = build_state
+        .module_names
+        .union(&build_state.deleted_modules)
+        .cloned()
+        .collect();
+    let get_deps = |path: &str| {
+        get_dep_modules(
+            &helpers::get_ast_path(path).to_string_lossy(),
+            package.namespace.to_suffix(),
+            package.modules.as_ref().unwrap(),
+            &all_mod,
+            package,
+            build_state,
+        )
+        .0
+    };
+
+    Some((
+        get_deps(&source_file.implementation.path),
+        source_file
+            .interface
+            .as_ref()
+            .map(|interface| get_deps(&interface.path))
+            .unwrap_or_default(),
+    ))
+}
+
+/// The modules of other packages that a module uses, while their package is not a (dev)
+/// dependency of the package of the module. These are left out of the dependencies of the module.
+pub fn get_undeclared_dep_modules(build_state: &BuildState, module_name: &str) -> AHashSet<String> {
+    let (module, source_file) = match build_state.get_module(module_name) {
+        Some(
+            module @ Module {
+                source_type: SourceType::SourceFile(source_file),
+                ..
+            },
+        ) => (module, source_file),
+        _ => return AHashSet::new(),
+    };
+    let package = match build_state.get_package(&module.package_name) {
+        Some(package) => package,
+        None => return AHashSet::new(),
+    };
+    let allowed_dependencies = package
+        .config
+        .bs_dependencies
+        .iter()
+        .chain(package.config.bs_dev_dependencies.iter())
+        .flatten()
+        .collect::<AHashSet<&String>>();
+
+    std::iter::once(&source_file.implementation.path)
+        .chain(source_file.interface.as_ref().map(|interface| &interface.path))
+        .flat_map(|path| read_ast_deps(&helpers::get_ast_path(path).to_string_lossy(), package))
+        .filter(|dep| !is_own_namespace_module(dep, package))
+        .map(|dep| get_referenced_module_name(&dep, build_state))
+        .filter(|dep| match build_state.get_module(dep) {
+            Some(dep_module) => {
+                dep_module.package_name != package.name
+                    && !allowed_dependencies.contains(&dep_module.package_name)
+            }
+            None => false,
+        })
+        .collect()
+}
+
+/// Collects the dependencies of all modules from their AST. Returns an error when a module uses a
+/// module of another package that is not public, or when a module that is not in a dev source
+/// folder uses a module that is only there for development.
+pub fn get_deps(build_state: &mut BuildState, deleted_modules: &AHashSet<String>) -> Result<(), String> {
+    collect_deps(build_state, deleted_modules, false)
+}
+
+/// Like `get_deps`, but for a dry run, where the modules that still need to be parsed don't have an
+/// AST yet. These modules keep the dependencies of the previous build.
+pub fn get_parsed_deps(
+    build_state: &mut BuildState,
+    deleted_modules: &AHashSet<String>,
+) -> Result<(), String> {
+    collect_deps(build_state, deleted_modules, true)
+}
+
+fn collect_deps(
+    build_state: &mut BuildState,
+    deleted_modules: &AHashSet<String>,
+    skip_unparsed: bool,
+) -> Result<(), String> {
+    let mut errors = "".to_string();
     let all_mod = &build_state.module_names.union(deleted_modules).cloned().collect();
     build_state
         .modules
         .par_iter()
         .map(|(module_name, module)| match &module.source_type {
-            SourceType::MlMap(_) => (module_name.to_string(), module.deps.to_owned()),
+            SourceType::MlMap(_) => (module_name.to_string(), module.deps.to_owned(), None, false),
             SourceType::SourceFile(source_file) => {
                 let package = build_state
                     .get_package(&module.package_name)
                     .expect("Package not found");
                 let ast_path = helpers::get_ast_path(&source_file.implementation.path);
-                if module.deps_dirty || !build_state.deps_initialized {
-                    let mut deps = get_dep_modules(
+                let is_skipped = skip_unparsed && !has_asts(source_file, package);
+                if (module.deps_dirty || !build_state.deps_initialized) && !is_skipped {
+                    let (mut deps, mut private_deps) = get_dep_modules(
                         &ast_path.to_string_lossy(),
                         package.namespace.to_suffix(),
                         package.modules.as_ref().unwrap(),
@@ -126,14 +397,16 @@ pub fn get_deps(build_state: &mut BuildState, deleted_modules: &AHashSet<String>
                     if let Some(interface) = &source_file.interface {
                         let iast_path = helpers::get_ast_path(&interface.path);
 
-                        deps.extend(get_dep_modules(
+                        let (interface_deps, interface_private_deps) = get_dep_modules(
                             &iast_path.to_string_lossy(),
                             package.namespace.to_suffix(),
                             package.modules.as_ref().unwrap(),
                             all_mod,
                             &package,
                             build_state,
-                        ))
+                        );
+                        deps.extend(interface_deps);
+                        private_deps.extend(interface_private_deps);
                     }
                     match &package.namespace {
                         packages::Namespace::NamespaceWithEntry { namespace: _, entry }
@@ -144,18 +417,49 @@ pub fn get_deps(build_state: &mut BuildState, deleted_modules: &AHashSet<String>
                         _ => (),
                     }
                     deps.remove(module_name);
-                    (module_name.to_string(), deps)
+
+                    let mut private_deps = private_deps.into_iter().collect::<Vec<String>>();
+                    private_deps.sort();
+                    let mut dev_deps = get_dev_dep_modules(module, &deps, package, build_state)
+                        .into_iter()
+                        .collect::<Vec<String>>();
+                    dev_deps.sort();
+                    let errors =
+                        private_deps
+                            .iter()
+                            .map(|private_dep| {
+                                format_private_dep_error(module_name, private_dep, package, build_state)
+                            })
+                            .chain(dev_deps.iter().map(|dev_dep| {
+                                format_dev_dep_error(module_name, dev_dep, package, build_state)
+                            }))
+                            .collect::<String>();
+                    (
+                        module_name.to_string(),
+                        deps,
+                        if errors.is_empty() { None } else { Some(errors) },
+                        false,
+                    )
                 } else {
-                    (module_name.to_string(), module.deps.to_owned())
+                    (module_name.to_string(), module.deps.to_owned(), None, is_skipped)
                 }
             }
         })
-        .collect::<Vec<(String, AHashSet<String>)>>()
+        .collect::<Vec<(String, AHashSet<String>, Option<String>, bool)>>()
         .into_iter()
-        .for_each(|(module_name, deps)| {
-            if let Some(module) = build_state.modules.get_mut(&module_name) {
+        .for_each(|(module_name, deps, error, is_skipped)| {
+            // a skipped module still needs to be checked in the next build
+            if let Some(module) = build_state.modules.get_mut(&module_name).filter(|_| !is_skipped) {
                 module.deps = deps.clone();
-                module.deps_dirty = false;
+                // keep the deps dirty when there is an error, so we check them again in the
+                // next (incremental) build
+                module.deps_dirty = error.is_some();
+                if let Some(error) = error {
+                    if let Some(package) = build_state.packages.get(&module.package_name) {
+                        logs::append(package, &error);
+                    }
+                    errors.push_str(&error);
+                }
             }
             deps.iter().for_each(|dep_name| {
                 if let Some(module) = build_state.modules.get_mut(dep_name) {
@@ -164,4 +468,161 @@ pub fn get_deps(build_state: &mut BuildState, deleted_modules: &AHashSet<String>
             });
         });
     build_state.deps_initialized = true;
+
+    if errors.is_empty() {
+        Ok(())
+    } else {
+        Err(errors)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config;
+    use ahash::AHashMap;
+
+    fn create_package(config: &str) -> packages::Package {
+        let config = serde_json::from_str::<config::Config>(config).unwrap();
+        packages::Package {
+            name: config.name.to_owned(),
+            namespace: config.get_namespace(),
+            config,
+            source_folders: AHashSet::new(),
+            source_files: None,
+            modules: None,
+            path: "".to_string(),
+            dirs: None,
+            private_modules: AHashSet::new(),
+            is_pinned_dep: false,
+            is_local_dep: true,
+            is_root: false,
+        }
+    }
+
+    fn create_module(package_name: &str, is_type_dev: bool) -> Module {
+        Module {
+            source_type: SourceType::MlMap(MlMap { parse_dirty: false }),
+            deps: AHashSet::new(),
+            dependents: AHashSet::new(),
+            package_name: package_name.to_string(),
+            is_type_dev,
+            compile_dirty: false,
+            dirty_reason: None,
+            last_compiled_cmi: None,
+            last_compiled_cmt: None,
+            deps_dirty: false,
+        }
+    }
+
+    fn create_build_state() -> BuildState {
+        let packages = [
+            create_package(
+                r#"{ "name": "main", "bs-dependencies": ["ui"], "bs-dev-dependencies": ["tester", "ui"] }"#,
+            ),
+            create_package(r#"{ "name": "tester" }"#),
+            create_package(r#"{ "name": "ui" }"#),
+            create_package(r#"{ "name": "forms", "namespace": "Forms" }"#),
+            create_package(r#"{ "name": "icons", "namespace": "Icons", "namespace-entry": "Icons" }"#),
+        ];
+        let mut build_state = BuildState::new(
+            "".to_string(),
+            "main".to_string(),
+            packages
+                .into_iter()
+                .map(|package| (package.name.to_owned(), package))
+                .collect::<AHashMap<String, packages::Package>>(),
+            None,
+            "".to_string(),
+            "".to_string(),
+        );
+        build_state.insert_module("App", create_module("main", false));
+        build_state.insert_module("AppTest", create_module("main", true));
+        build_state.insert_module("TestUtils", create_module("main", true));
+        build_state.insert_module("Expect", create_module("tester", false));
+        build_state.insert_module("Button", create_module("ui", false));
+        build_state
+    }
+
+    fn get_sorted_dev_dep_modules(build_state: &BuildState, module_name: &str, deps: &[&str]) -> Vec<String> {
+        let module = build_state.get_module(module_name).unwrap();
+        let package = build_state.get_package(&module.package_name).unwrap();
+        let deps = deps
+            .iter()
+            .map(|dep| dep.to_string())
+            .collect::<AHashSet<String>>();
+        let mut dev_dep_modules = get_dev_dep_modules(module, &deps, package, build_state)
+            .into_iter()
+            .collect::<Vec<String>>();
+        dev_dep_modules.sort();
+        dev_dep_modules
+    }
+
+    #[test]
+    fn test_get_dev_dep_modules_of_dev_source_folders() {
+        let build_state = create_build_state();
+        assert_eq!(
+            get_sorted_dev_dep_modules(&build_state, "App", &["TestUtils", "Button"]),
+            vec!["TestUtils"]
+        );
+        assert!(get_sorted_dev_dep_modules(&build_state, "AppTest", &["TestUtils"]).is_empty());
+    }
+
+    #[test]
+    fn test_get_dev_dep_modules_of_dev_dependencies() {
+        let build_state = create_build_state();
+        // ui is also one of the bs-dependencies, so it can be used from everywhere
+        assert_eq!(
+            get_sorted_dev_dep_modules(&build_state, "App", &["Expect", "Button"]),
+            vec!["Expect"]
+        );
+        assert!(get_sorted_dev_dep_modules(&build_state, "AppTest", &["Expect", "Button"]).is_empty());
+    }
+
+    #[test]
+    fn test_get_private_dep_modules_with_own_namespace() {
+        let mut build_state = create_build_state();
+        build_state.insert_module("Button-Forms", create_module("forms", false));
+        let forms = build_state.packages.get_mut("forms").unwrap();
+        forms.modules = Some(AHashSet::from_iter(["Button-Forms".to_string()]));
+        let ui = build_state.packages.get_mut("ui").unwrap();
+        ui.private_modules = AHashSet::from_iter(["Button".to_string()]);
+        let deps = AHashSet::from_iter(["Button".to_string()]);
+        let allowed_dependencies = AHashSet::from_iter(["ui".to_string()]);
+
+        assert_eq!(
+            get_private_dep_modules(
+                &deps,
+                &allowed_dependencies,
+                build_state.get_package("main").unwrap(),
+                &build_state
+            ),
+            AHashSet::from_iter(["Button".to_string()])
+        );
+        // Button is the own Button-Forms in the forms namespace
+        assert!(get_private_dep_modules(
+            &deps,
+            &allowed_dependencies,
+            build_state.get_package("forms").unwrap(),
+            &build_state
+        )
+        .is_empty());
+    }
+
+    #[test]
+    fn test_get_referenced_module_name() {
+        let build_state = create_build_state();
+        assert_eq!(get_referenced_module_name("Button", &build_state), "Button");
+        assert_eq!(get_referenced_module_name("Button.make", &build_state), "Button");
+        assert_eq!(
+            get_referenced_module_name("Forms.Input", &build_state),
+            "Input-Forms"
+        );
+        assert_eq!(
+            get_referenced_module_name("@Icons.Arrow", &build_state),
+            "Arrow-@Icons"
+        );
+        // the entry of a namespace is a regular module
+        assert_eq!(get_referenced_module_name("Icons.Arrow", &build_state), "Icons");
+    }
 }
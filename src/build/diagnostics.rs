@@ -0,0 +1,221 @@
+use super::logs;
+use crate::helpers;
+use regex::Regex;
+use serde::Serialize;
+use std::io::{stdout, Write};
+
+// The diagnostics of bsc are only available as the (coloured) text it writes to stderr. This
+// module turns that output into one record per error or warning, so tooling doesn't need to
+// scrape the `.compiler.log` files.
+//
+// A diagnostic from bsc looks like this (without the colours):
+//
+//   We've found a bug for you!
+//   /path/to/src/Main.res:4:8-24
+//
+//   3 │
+//   4 │ Js.log(InternalDep.value)
+//   5 │
+//
+//   The module or file InternalDep can't be found.
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, clap::ValueEnum)]
+pub enum Reporter {
+    /// Human readable output
+    Human,
+    /// One JSON record per diagnostic. A JSON array for build, newline delimited JSON for watch
+    Json,
+}
+
+impl Reporter {
+    pub fn is_json(&self) -> bool {
+        matches!(self, Reporter::Json)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Severity {
+    Error,
+    Warning,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct Position {
+    pub line: u32,
+    pub column: u32,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct Range {
+    pub start: Position,
+    pub end: Position,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct Diagnostic {
+    pub file: String,
+    pub range: Range,
+    pub severity: Severity,
+    pub warning_number: Option<u32>,
+    pub package: String,
+    pub module: String,
+    pub message: String,
+}
+
+fn parse_location(line: &str) -> Option<(String, Range)> {
+    // file:line:column, file:line:column-column or file:line:column-line:column
+    let re = Regex::new(r"^\s*(.+):(\d+):(\d+)(?:-(\d+)(?::(\d+))?)?\s*$").expect("Could not create regex");
+    let captures = re.captures(line)?;
+    let number = |index: usize| captures.get(index).and_then(|m| m.as_str().parse::<u32>().ok());
+
+    let start = Position {
+        line: number(2)?,
+        column: number(3)?,
+    };
+    let end = match (number(4), number(5)) {
+        (Some(end_line), Some(end_column)) => Position {
+            line: end_line,
+            column: end_column,
+        },
+        (Some(end_column), None) => Position {
+            line: start.line,
+            column: end_column,
+        },
+        _ => start.to_owned(),
+    };
+
+    Some((captures[1].to_string(), Range { start, end }))
+}
+
+fn parse_header(line: &str) -> Option<(Severity, Option<u32>)> {
+    let re = Regex::new(r"^\s*(?:(We've found a bug for you!|Syntax error!)|Warning number (\d+)( \(configured as error\))?)\s*$")
+        .expect("Could not create regex");
+    let captures = re.captures(line)?;
+    match captures.get(2).and_then(|m| m.as_str().parse::<u32>().ok()) {
+        Some(warning_number) if captures.get(3).is_some() => Some((Severity::Error, Some(warning_number))),
+        Some(warning_number) => Some((Severity::Warning, Some(warning_number))),
+        None => Some((Severity::Error, None)),
+    }
+}
+
+/// Parses the output of bsc (for parsing or compiling a single module) into diagnostics. Output
+/// that doesn't look like a bsc diagnostic is ignored.
+pub fn parse(output: &str, package_name: &str, module_name: &str) -> Vec<Diagnostic> {
+    let code_frame = Regex::new(r"^\s*(\d+|\.+)?\s*│").expect("Could not create regex");
+    let output = logs::escape_colours(output);
+    let lines = output.lines().collect::<Vec<&str>>();
+
+    let headers = lines
+        .iter()
+        .enumerate()
+        .filter_map(|(index, line)| parse_header(line).map(|header| (index, header)))
+        .collect::<Vec<(usize, (Severity, Option<u32>))>>();
+
+    headers
+        .iter()
+        .enumerate()
+        .filter_map(|(nth, (index, (severity, warning_number)))| {
+            let end = headers
+                .get(nth + 1)
+                .map(|(index, _)| *index)
+                .unwrap_or(lines.len());
+            let mut block = lines[index + 1..end]
+                .iter()
+                .skip_while(|line| line.trim().is_empty());
+            let (file, range) = parse_location(block.next()?)?;
+            let message = block
+                .filter(|line| !code_frame.is_match(line))
+                .map(|line| line.trim_end())
+                .collect::<Vec<&str>>()
+                .join("\n");
+            Some(Diagnostic {
+                file,
+                range,
+                severity: *severity,
+                warning_number: *warning_number,
+                package: package_name.to_string(),
+                module: helpers::format_namespaced_module_name(module_name),
+                message: dedent(message.trim_matches('\n')),
+            })
+        })
+        .collect()
+}
+
+// bsc indents the message with two spaces
+fn dedent(message: &str) -> String {
+    message
+        .lines()
+        .map(|line| line.strip_prefix("  ").unwrap_or(line))
+        .collect::<Vec<&str>>()
+        .join("\n")
+}
+
+/// Prints all diagnostics as a single JSON array
+pub fn print_json(diagnostics: &[Diagnostic]) {
+    println!(
+        "{}",
+        serde_json::to_string(diagnostics).expect("Could not serialize diagnostics")
+    );
+}
+
+/// Prints every diagnostic as JSON on its own line, so a consumer can process them as a stream
+pub fn print_ndjson(diagnostics: &[Diagnostic]) {
+    let mut stdout = stdout().lock();
+    for diagnostic in diagnostics {
+        if let Ok(line) = serde_json::to_string(diagnostic) {
+            let _ = writeln!(stdout, "{}", line);
+        }
+    }
+    let _ = stdout.flush();
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_error() {
+        let output = "\n  \u{1b}[1;31mWe've found a bug for you!\u{1b}[0m\n  \u{1b}[36m/packages/main/src/Main.res\u{1b}[0m:\u{1b}[2m4:8-24\u{1b}[0m\n\n  3 \u{1b}[2m│\u{1b}[0m \n  \u{1b}[1;31m4\u{1b}[0m \u{1b}[2m│\u{1b}[0m Js.log(InternalDep.value)\n  5 \u{1b}[2m│\u{1b}[0m \n\n  \u{1b}[1;33mThe module or file InternalDep can't be found.\u{1b}[0m\n  - If it's a third-party dependency:\n\n";
+        let diagnostics = parse(output, "main", "Main");
+
+        assert_eq!(
+            diagnostics,
+            vec![Diagnostic {
+                file: "/packages/main/src/Main.res".to_string(),
+                range: Range {
+                    start: Position { line: 4, column: 8 },
+                    end: Position { line: 4, column: 24 },
+                },
+                severity: Severity::Error,
+                warning_number: None,
+                package: "main".to_string(),
+                module: "Main".to_string(),
+                message:
+                    "The module or file InternalDep can't be found.\n- If it's a third-party dependency:"
+                        .to_string(),
+            }]
+        );
+    }
+
+    #[test]
+    fn test_parse_warnings() {
+        let output = "\n  Warning number 27\n  /src/Foo.res:1:9-3:2\n\n  1 │ let f = x => 1\n\n  unused variable x.\n\n  Warning number 32 (configured as error)\n  /src/Foo.res:5:5\n\n  unused value y.\n";
+        let diagnostics = parse(output, "pkg", "Foo-Ns");
+
+        assert_eq!(diagnostics.len(), 2);
+        assert_eq!(diagnostics[0].severity, Severity::Warning);
+        assert_eq!(diagnostics[0].warning_number, Some(27));
+        assert_eq!(diagnostics[0].range.end, Position { line: 3, column: 2 });
+        assert_eq!(diagnostics[0].message, "unused variable x.");
+        assert_eq!(diagnostics[0].module, "Ns.Foo");
+        assert_eq!(diagnostics[1].severity, Severity::Error);
+        assert_eq!(diagnostics[1].warning_number, Some(32));
+        assert_eq!(diagnostics[1].range.start, diagnostics[1].range.end);
+    }
+
+    #[test]
+    fn test_parse_other_output() {
+        assert_eq!(parse("Some other error", "pkg", "Foo"), vec![]);
+    }
+}
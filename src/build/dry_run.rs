@@ -0,0 +1,176 @@
+use super::build_types::*;
+use super::compile::{mark_modules_with_deleted_deps_dirty, mark_modules_with_expired_deps_dirty};
+use super::{deps, get_entries, initialize_build, retain_entry_modules, retain_packages};
+use crate::helpers;
+use ahash::AHashSet;
+use anyhow::{anyhow, Result};
+use std::path::Path;
+
+// A dry run goes through the same steps as a build up to the point where bsc would run, and
+// reports what would be parsed and compiled, and why. Nothing is parsed or compiled, and no
+// compiler assets are removed. The dependencies of the modules that are not parsed yet are the
+// ones of the last build.
+
+fn get_parse_reason(package: &super::packages::Package, path: &str) -> &'static str {
+    let ast_path = Path::new(&package.get_build_path()).join(helpers::get_ast_path(path));
+    if ast_path.exists() {
+        "the source changed since it was parsed"
+    } else {
+        "it was not parsed yet"
+    }
+}
+
+// the source files that would be parsed, with the reason
+fn get_parse_dirty(build_state: &BuildState) -> Vec<(String, &'static str)> {
+    let mut parse_dirty = build_state
+        .modules
+        .values()
+        .filter_map(|module| match &module.source_type {
+            SourceType::SourceFile(source_file) => {
+                let package = build_state.get_package(&module.package_name)?;
+                let implementation = Some(&source_file.implementation)
+                    .filter(|implementation| implementation.parse_dirty)
+                    .map(|implementation| &implementation.path);
+                let interface = source_file
+                    .interface
+                    .as_ref()
+                    .filter(|interface| interface.parse_dirty)
+                    .map(|interface| &interface.path);
+                Some(
+                    implementation
+                        .into_iter()
+                        .chain(interface)
+                        .map(|path| {
+                            let path_buf = Path::new(&package.path).join(path);
+                            let relative_path = path_buf
+                                .strip_prefix(&build_state.project_root)
+                                .unwrap_or(&path_buf);
+                            (
+                                relative_path.to_string_lossy().to_string(),
+                                get_parse_reason(package, path),
+                            )
+                        })
+                        .collect::<Vec<(String, &'static str)>>(),
+                )
+            }
+            SourceType::MlMap(_) => None,
+        })
+        .flatten()
+        .collect::<Vec<(String, &'static str)>>();
+    parse_dirty.sort();
+    parse_dirty
+}
+
+fn get_compile_dirty(build_state: &BuildState) -> AHashSet<String> {
+    build_state
+        .modules
+        .iter()
+        .filter(|(_, module)| module.compile_dirty)
+        .map(|(module_name, _)| module_name.to_owned())
+        .collect()
+}
+
+// the modules that depend on the compiled modules are compiled as well, but only when the
+// interface (.cmi) of a module they depend on changes
+fn get_dependents(build_state: &BuildState, compile_dirty: &AHashSet<String>) -> AHashSet<String> {
+    let mut dependents = AHashSet::new();
+    let mut queue = compile_dirty.iter().cloned().collect::<Vec<String>>();
+    while let Some(module_name) = queue.pop() {
+        if let Some(module) = build_state.get_module(&module_name) {
+            for dependent in module.dependents.iter() {
+                if !compile_dirty.contains(dependent) && dependents.insert(dependent.to_owned()) {
+                    queue.push(dependent.to_owned());
+                }
+            }
+        }
+    }
+    dependents
+}
+
+fn format_reasons<T: AsRef<str>>(reasons: &[(String, T)]) -> String {
+    reasons
+        .iter()
+        .map(|(name, reason)| format!("  {}: {}", name, reason.as_ref()))
+        .collect::<Vec<String>>()
+        .join("\n")
+}
+
+/// Prints the source files that would be parsed and the modules that would be compiled by a build,
+/// with the reason
+pub fn run(
+    filter: &Option<regex::Regex>,
+    path: &str,
+    bsc_path: Option<String>,
+    build_dev_deps: bool,
+    packages: &[String],
+    entries: &[String],
+) -> Result<()> {
+    let mut build_state = initialize_build(None, filter, false, path, bsc_path, build_dev_deps, true, false)
+        .map_err(|e| anyhow!("Could not initialize build. Error: {e}"))?;
+    if !packages.is_empty() {
+        retain_packages(&mut build_state, packages, build_dev_deps)?;
+    }
+    build_state.entries = get_entries(&build_state, entries)?;
+
+    let parse_dirty = get_parse_dirty(&build_state);
+
+    let deleted_modules = build_state.deleted_modules.to_owned();
+    if let Err(errors) = deps::get_parsed_deps(&mut build_state, &deleted_modules) {
+        println!("{}", errors);
+    }
+    if let Some(entries) = build_state.entries.to_owned() {
+        retain_entry_modules(&mut build_state, &entries);
+    }
+    mark_modules_with_expired_deps_dirty(&mut build_state);
+    mark_modules_with_deleted_deps_dirty(&mut build_state);
+    let compile_dirty = get_compile_dirty(&build_state);
+
+    let mut compile = compile_dirty
+        .iter()
+        .filter_map(|module_name| {
+            let module = build_state.get_module(module_name)?;
+            let reason = module
+                .dirty_reason
+                .to_owned()
+                .unwrap_or(DirtyReason::SourceChanged);
+            Some((module_name.to_owned(), reason.to_string()))
+        })
+        .collect::<Vec<(String, String)>>();
+    compile.sort();
+    let compile = compile
+        .into_iter()
+        .map(|(module_name, reason)| (helpers::format_namespaced_module_name(&module_name), reason))
+        .collect::<Vec<(String, String)>>();
+
+    let mut dependents = get_dependents(&build_state, &compile_dirty)
+        .into_iter()
+        .collect::<Vec<String>>();
+    dependents.sort();
+    let dependents = dependents
+        .into_iter()
+        .map(|module_name| {
+            (
+                helpers::format_namespaced_module_name(&module_name),
+                "when the interface of a module it depends on changes".to_string(),
+            )
+        })
+        .collect::<Vec<(String, String)>>();
+
+    if parse_dirty.is_empty() && compile.is_empty() {
+        println!("Nothing to parse or compile");
+        return Ok(());
+    }
+    println!("Would parse {} source files:", parse_dirty.len());
+    if !parse_dirty.is_empty() {
+        println!("{}", format_reasons(&parse_dirty));
+    }
+    println!("\nWould compile {} modules:", compile.len());
+    if !compile.is_empty() {
+        println!("{}", format_reasons(&compile));
+    }
+    if !dependents.is_empty() {
+        println!("\nCould also compile {} modules:", dependents.len());
+        println!("{}", format_reasons(&dependents));
+    }
+    Ok(())
+}
@@ -0,0 +1,272 @@
+use super::build_types::*;
+use super::logs;
+use super::packages;
+use crate::helpers;
+use ahash::AHashSet;
+use log::debug;
+use rayon::prelude::*;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::Path;
+use std::process::Command;
+use std::time::SystemTime;
+
+// Generators are the `generators` from the bsconfig spec. The package config defines the rules
+// (a name and a command), and the source folders define edges that use these rules to create
+// source files out of other files (for instance `.graphql` files).
+//
+// We keep the hashes of the inputs and outputs of every edge in the build folder of the package,
+// so we only run a generator again when one of its inputs changed, or when one of its outputs
+// was changed or removed by something else.
+
+static GENERATORS_STATE_FILE: &str = ".generators.json";
+
+type FileHashes = BTreeMap<String, Option<String>>;
+
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq)]
+struct EdgeState {
+    inputs: FileHashes,
+    outputs: FileHashes,
+}
+
+type GeneratorsState = BTreeMap<String, EdgeState>;
+
+#[derive(Debug, Clone)]
+struct Edge {
+    package_name: String,
+    key: String,
+    command: String,
+    // these are relative to the package root
+    inputs: Vec<String>,
+    outputs: Vec<String>,
+}
+
+fn get_state_path(package: &packages::Package) -> String {
+    package.get_build_path() + "/" + GENERATORS_STATE_FILE
+}
+
+fn read_state(package: &packages::Package) -> GeneratorsState {
+    fs::read_to_string(get_state_path(package))
+        .ok()
+        .and_then(|contents| serde_json::from_str::<GeneratorsState>(&contents).ok())
+        .unwrap_or_default()
+}
+
+fn write_state(package: &packages::Package, state: &GeneratorsState) {
+    if let Ok(contents) = serde_json::to_string(state) {
+        let _ = fs::write(get_state_path(package), contents);
+    }
+}
+
+fn hash_files(package: &packages::Package, files: &[String]) -> FileHashes {
+    files
+        .iter()
+        .map(|file| {
+            (
+                file.to_owned(),
+                helpers::compute_file_hash(&Path::new(&package.path).join(file))
+                    .map(|hash| hash.to_hex().to_string()),
+            )
+        })
+        .collect()
+}
+
+// Only generators of local packages are run, dependencies from node_modules are expected to ship
+// their generated files (this is also what bsb does). Edges of dev source folders only run when
+// we build with the dev dependencies.
+fn get_edges(package: &packages::Package, build_dev_deps: bool) -> Result<Vec<Edge>, String> {
+    if !package.is_local_dep {
+        return Ok(vec![]);
+    }
+
+    package
+        .source_folders
+        .iter()
+        .filter(|source| build_dev_deps || source.type_.as_deref() != Some("dev"))
+        .flat_map(|source| {
+            source
+                .generators
+                .to_owned()
+                .unwrap_or_default()
+                .into_iter()
+                .map(|generator| (source.dir.to_owned(), generator))
+        })
+        .map(|(dir, generator)| {
+            let command = package
+                .config
+                .get_generator_command(&generator.name)
+                .ok_or(format!(
+                    "Generator \"{}\" used in \"{}\" is not defined in the config of package {}",
+                    generator.name, dir, package.name
+                ))?;
+            let to_package_path = |file: String| Path::new(&dir).join(file).to_string_lossy().to_string();
+            let outputs = generator
+                .get_outputs()
+                .into_iter()
+                .map(to_package_path)
+                .collect::<Vec<String>>();
+            let inputs = generator
+                .get_inputs()
+                .into_iter()
+                .map(to_package_path)
+                .collect::<Vec<String>>();
+
+            Ok(Edge {
+                package_name: package.name.to_owned(),
+                key: generator.name.to_owned() + ":" + &outputs.join(" "),
+                command,
+                inputs,
+                outputs,
+            })
+        })
+        .collect()
+}
+
+fn run_edge(package: &packages::Package, edge: &Edge) -> Result<(), String> {
+    debug!("Running generator: {}", edge.key);
+    let command = edge
+        .command
+        .replace("$in", &edge.inputs.join(" "))
+        .replace("$out", &edge.outputs.join(" "));
+
+    let (shell, shell_arg) = if cfg!(windows) {
+        ("cmd", "/C")
+    } else {
+        ("sh", "-c")
+    };
+    let output = Command::new(shell)
+        .current_dir(&package.path)
+        .args([shell_arg, &command])
+        .output();
+
+    match output {
+        Ok(output) if output.status.success() => Ok(()),
+        Ok(output) => Err(format!(
+            "Error in {}: generator \"{}\" failed:\n{}{}",
+            package.name,
+            command,
+            String::from_utf8_lossy(&output.stderr),
+            String::from_utf8_lossy(&output.stdout)
+        )),
+        Err(e) => Err(format!(
+            "Error in {}: could not run generator \"{}\". Error: {}",
+            package.name, command, e
+        )),
+    }
+}
+
+// when a generator rewrites one of the source files, we need to parse it again
+fn mark_output_dirty(build_state: &mut BuildState, package_name: &str, output: &str) {
+    let (namespace, last_modified) = match build_state.get_package(package_name) {
+        Some(package) => (
+            package.namespace.to_owned(),
+            fs::metadata(Path::new(&package.path).join(output))
+                .and_then(|metadata| metadata.modified())
+                .unwrap_or_else(|_| SystemTime::now()),
+        ),
+        None => return,
+    };
+    let module_name = helpers::file_path_to_module_name(output, &namespace);
+    if let Some(Module {
+        source_type: SourceType::SourceFile(source_file),
+        ..
+    }) = build_state.modules.get_mut(&module_name)
+    {
+        if source_file.implementation.path == output {
+            source_file.implementation.parse_dirty = true;
+            source_file.implementation.last_modified = last_modified;
+        }
+        if let Some(interface) = source_file.interface.as_mut() {
+            if interface.path == output {
+                interface.parse_dirty = true;
+                interface.last_modified = last_modified;
+            }
+        }
+    }
+}
+
+/// Runs the generators of which the inputs (or outputs) changed since the last time they ran.
+/// Returns the number of generators that ran.
+pub fn run(build_state: &mut BuildState, build_dev_deps: bool) -> Result<usize, String> {
+    let mut edges: Vec<Edge> = vec![];
+    for package in build_state.packages.values() {
+        edges.extend(get_edges(package, build_dev_deps)?);
+    }
+    if edges.is_empty() {
+        return Ok(0);
+    }
+
+    let mut states = edges
+        .iter()
+        .map(|edge| edge.package_name.to_owned())
+        .collect::<AHashSet<String>>()
+        .into_iter()
+        .map(|package_name| {
+            let package = build_state.get_package(&package_name).expect("Package not found");
+            (package_name, read_state(package))
+        })
+        .collect::<BTreeMap<String, GeneratorsState>>();
+
+    let results = edges
+        .par_iter()
+        .filter_map(|edge| {
+            let package = build_state
+                .get_package(&edge.package_name)
+                .expect("Package not found");
+            let previous = states
+                .get(&edge.package_name)
+                .and_then(|state| state.get(&edge.key));
+            let current = EdgeState {
+                inputs: hash_files(package, &edge.inputs),
+                outputs: hash_files(package, &edge.outputs),
+            };
+            let outputs_missing = current.outputs.values().any(|hash| hash.is_none());
+            if !outputs_missing && previous == Some(&current) {
+                return None;
+            }
+
+            let result = run_edge(package, edge).map(|_| EdgeState {
+                inputs: current.inputs.to_owned(),
+                outputs: hash_files(package, &edge.outputs),
+            });
+            Some((edge, current, result))
+        })
+        .collect::<Vec<_>>();
+
+    let mut errors = "".to_string();
+    for (edge, before, result) in results.iter() {
+        match result {
+            Ok(after) => {
+                for output in edge.outputs.iter() {
+                    if before.outputs.get(output) != after.outputs.get(output) {
+                        mark_output_dirty(build_state, &edge.package_name, output);
+                    }
+                }
+                if let Some(state) = states.get_mut(&edge.package_name) {
+                    state.insert(edge.key.to_owned(), after.to_owned());
+                }
+            }
+            Err(err) => {
+                let package = build_state
+                    .get_package(&edge.package_name)
+                    .expect("Package not found");
+                logs::append(package, err);
+                errors.push_str(err);
+                errors.push('\n');
+            }
+        }
+    }
+
+    for (package_name, state) in states.iter() {
+        if let Some(package) = build_state.get_package(package_name) {
+            write_state(package, state);
+        }
+    }
+
+    if errors.is_empty() {
+        Ok(results.len())
+    } else {
+        Err(errors)
+    }
+}
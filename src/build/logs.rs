@@ -23,7 +23,7 @@ fn get_log_file_path(package: &packages::Package, subfolder: Location) -> String
     build_folder.to_owned() + "/.compiler.log"
 }
 
-fn escape_colours(str: &str) -> String {
+pub fn escape_colours(str: &str) -> String {
     let re = Regex::new(r"[\u001b\u009b]\[[()#;?]*(?:[0-9]{1,4}(?:;[0-9]{0,4})*)?[0-9A-ORZcf-nqry=><]")
         .expect("Could not create regex");
     re.replace_all(str, "").to_string()
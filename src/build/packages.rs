@@ -18,6 +18,7 @@ use std::time::SystemTime;
 #[derive(Debug, Clone)]
 pub struct SourceFileMeta {
     pub modified: SystemTime,
+    pub is_type_dev: bool,
 }
 
 #[derive(Debug, Clone)]
@@ -58,6 +59,9 @@ pub struct Package {
     // canonicalized dir of the package
     pub path: String,
     pub dirs: Option<AHashSet<PathBuf>>,
+    // the modules that are not in the `public` modules of their source folder, these can only be
+    // used from within the package itself
+    pub private_modules: AHashSet<String>,
     pub is_pinned_dep: bool,
     pub is_local_dep: bool,
     pub is_root: bool,
@@ -106,6 +110,24 @@ impl Package {
             + ".mlmap"
     }
 
+    /// The files (relative to the package root) that are the outputs of the generators of the
+    /// source folders of this package
+    pub fn get_generated_files(&self) -> Vec<String> {
+        self.source_folders
+            .iter()
+            .flat_map(|source| {
+                source
+                    .generators
+                    .to_owned()
+                    .unwrap_or_default()
+                    .iter()
+                    .flat_map(|generator| generator.get_outputs())
+                    .map(|output| Path::new(&source.dir).join(output).to_string_lossy().to_string())
+                    .collect::<Vec<String>>()
+            })
+            .collect()
+    }
+
     pub fn get_mlmap_compile_path(&self) -> String {
         self.get_build_path()
             + "/"
@@ -138,6 +160,7 @@ fn matches_filter(filter: &Option<regex::Regex>, path: &str) -> bool {
 
 pub fn read_folders(
     filter: &Option<regex::Regex>,
+    config: &config::Config,
     package_dir: &Path,
     path: &Path,
     recurse: bool,
@@ -150,6 +173,7 @@ pub fn read_folders(
             path.to_owned(),
             SourceFileMeta {
                 modified: meta.modified().unwrap(),
+                is_type_dev: false,
             },
         )
     });
@@ -162,7 +186,11 @@ pub fn read_folders(
         let path_ext = entry_path_buf.extension().and_then(|x| x.to_str());
         let new_path = path_buf.join(&name);
         if metadata.file_type().is_dir() && recurse {
-            match read_folders(filter, package_dir, &new_path, recurse) {
+            if config.is_ignored_dir(&new_path) {
+                log::info!("Ignored: {:?}", new_path);
+                continue;
+            }
+            match read_folders(filter, config, package_dir, &new_path, recurse) {
                 Ok(s) => map.extend(s),
                 Err(e) => log::error!("Could not read directory: {}", e),
             }
@@ -177,6 +205,7 @@ pub fn read_folders(
                         path.to_string_lossy().to_string(),
                         SourceFileMeta {
                             modified: metadata.modified().unwrap(),
+                            is_type_dev: false,
                         },
                     );
                 }
@@ -194,11 +223,19 @@ pub fn read_folders(
 /// Given a projects' root folder and a `config::Source`, this recursively creates all the
 /// sources in a flat list. In the process, it removes the children, as they are being resolved
 /// because of the recursiveness. So you get a flat list of files back, retaining the type_ and
-/// whether it needs to recurse into all structures
-fn get_source_dirs(source: config::Source, sub_path: Option<PathBuf>) -> AHashSet<config::PackageSource> {
+/// whether it needs to recurse into all structures. Folders listed in `ignored-dirs` are skipped,
+/// together with their children.
+fn get_source_dirs(
+    source: config::Source,
+    sub_path: Option<PathBuf>,
+    config: &config::Config,
+) -> AHashSet<config::PackageSource> {
     let mut source_folders: AHashSet<config::PackageSource> = AHashSet::new();
 
     let source_folder = source.to_qualified_without_children(sub_path.to_owned());
+    if config.is_ignored_dir(Path::new(&source_folder.dir)) {
+        return source_folders;
+    }
     source_folders.insert(source_folder.to_owned());
 
     let (subdirs, full_recursive) = match source.to_owned() {
@@ -220,7 +257,11 @@ fn get_source_dirs(source: config::Source, sub_path: Option<PathBuf>) -> AHashSe
             .unwrap_or(vec![])
             .par_iter()
             .map(|subsource| {
-                get_source_dirs(subsource.set_type(source.get_type()), Some(sub_path.to_owned()))
+                get_source_dirs(
+                    subsource.set_type(source.get_type()),
+                    Some(sub_path.to_owned()),
+                    config,
+                )
             })
             .collect::<Vec<AHashSet<config::PackageSource>>>()
             .into_iter()
@@ -298,6 +339,9 @@ pub fn read_dependency(
 ///    registerd for the parent packages. Especially relevant for peerDependencies.
 /// 2. In parallel performs IO to read the dependencies config and
 ///    recursively continues operation for their dependencies as well.
+///
+/// With `build_dev_deps`, the bs-dev-dependencies of the root and local packages are read as well.
+/// Those of dependencies from node_modules are not, as they are usually not installed.
 fn read_dependencies(
     registered_dependencies_set: &mut AHashSet<String>,
     parent_config: &config::Config,
@@ -305,12 +349,19 @@ fn read_dependencies(
     project_root: &str,
     workspace_root: Option<String>,
     show_progress: bool,
+    build_dev_deps: bool,
 ) -> Vec<Dependency> {
+    let dev_dependencies = if build_dev_deps && !parent_path.contains("node_modules") {
+        parent_config.bs_dev_dependencies.to_owned().unwrap_or_default()
+    } else {
+        vec![]
+    };
     return parent_config
         .bs_dependencies
         .to_owned()
         .unwrap_or_default()
         .iter()
+        .chain(dev_dependencies.iter())
         .filter_map(|package_name| {
             if registered_dependencies_set.contains(package_name) {
                 None
@@ -366,7 +417,8 @@ fn read_dependencies(
                 &canonical_path,
                 project_root,
                 workspace_root.to_owned(),
-                show_progress
+                show_progress,
+                build_dev_deps,
             );
 
             Dependency {
@@ -411,12 +463,12 @@ pub fn read_package_name(package_dir: &str) -> Result<String> {
 
 fn make_package(config: config::Config, package_path: &str, is_pinned_dep: bool, is_root: bool) -> Package {
     let source_folders = match config.sources.to_owned() {
-        Some(config::OneOrMore::Single(source)) => get_source_dirs(source, None),
+        Some(config::OneOrMore::Single(source)) => get_source_dirs(source, None, &config),
         Some(config::OneOrMore::Multiple(sources)) => {
             let mut source_folders: AHashSet<config::PackageSource> = AHashSet::new();
             sources
                 .iter()
-                .map(|source| get_source_dirs(source.to_owned(), None))
+                .map(|source| get_source_dirs(source.to_owned(), None, &config))
                 .collect::<Vec<AHashSet<config::PackageSource>>>()
                 .into_iter()
                 .for_each(|source| source_folders.extend(source));
@@ -446,6 +498,7 @@ fn make_package(config: config::Config, package_path: &str, is_pinned_dep: bool,
             .to_string_lossy()
             .to_string(),
         dirs: None,
+        private_modules: AHashSet::new(),
         is_pinned_dep,
         is_local_dep: !package_path.contains("node_modules"),
         is_root,
@@ -456,6 +509,7 @@ fn read_packages(
     project_root: &str,
     workspace_root: Option<String>,
     show_progress: bool,
+    build_dev_deps: bool,
 ) -> Result<AHashMap<String, Package>> {
     let root_config = read_config(project_root)?;
 
@@ -472,6 +526,7 @@ fn read_packages(
         project_root,
         workspace_root,
         show_progress,
+        build_dev_deps,
     ));
     dependencies.iter().for_each(|d| {
         if !map.contains_key(&d.name) {
@@ -489,14 +544,15 @@ fn read_packages(
 /// can be specified as being fully recursive (`{ subdirs: true }`). This wrapper pulls out that
 /// data from the config and pushes it forwards. Another thing is the 'type_', some files / folders
 /// can be marked with the type 'dev'. Which means that they may not be around in the distributed
-/// NPM package. The file reader allows for this, just warns when this happens.
+/// NPM package. These folders are skipped unless `build_dev_sources` is set.
 /// TODO -> Check whether we actually need the `fs::Metadata`
 pub fn get_source_files(
     package_name: &String,
+    config: &config::Config,
     package_dir: &Path,
     filter: &Option<regex::Regex>,
     source: &config::PackageSource,
-    build_dev_deps: bool,
+    build_dev_sources: bool,
 ) -> AHashMap<String, SourceFileMeta> {
     let mut map: AHashMap<String, SourceFileMeta> = AHashMap::new();
 
@@ -510,23 +566,132 @@ pub fn get_source_files(
     };
 
     let path_dir = Path::new(&source.dir);
-    match (build_dev_deps, type_) {
+    match (build_dev_sources, type_) {
         (false, Some(type_)) if type_ == "dev" => (),
-        _ => match read_folders(filter, package_dir, path_dir, recurse) {
-            Ok(files) => map.extend(files),
-
-            Err(_e) => log::error!(
-                "Could not read folder: {:?}. Specified in dependency: {}, located {:?}...",
-                path_dir.to_path_buf().into_os_string(),
-                package_name,
-                package_dir
-            ),
-        },
+        _ => {
+            match read_folders(filter, config, package_dir, path_dir, recurse) {
+                Ok(files) => map.extend(filter_source_files(source, files)),
+
+                Err(_e) => log::error!(
+                    "Could not read folder: {:?}. Specified in dependency: {}, located {:?}...",
+                    path_dir.to_path_buf().into_os_string(),
+                    package_name,
+                    package_dir
+                ),
+            }
+            map.extend(get_generated_source_files(filter, package_dir, source));
+        }
     };
 
+    let is_type_dev = type_.as_ref().is_some_and(|type_| type_ == "dev");
+    map.values_mut().for_each(|meta| meta.is_type_dev = is_type_dev);
     map
 }
 
+// like bsb, a file in `files` or `excludes` is relative to the source folder, so a bare file name
+// doesn't match the files with that name in the subdirectories
+fn matches_source_file(relative_path: &Path, file: &str) -> bool {
+    relative_path == Path::new(file)
+}
+
+/// Applies the `files` of a source folder to the files that were found in it. This is either an
+/// explicit list of files, or a File-Object with `excludes` and / or a regex (`slow-re`) that
+/// needs to match the file name.
+fn filter_source_files(
+    source: &config::PackageSource,
+    files: AHashMap<String, SourceFileMeta>,
+) -> AHashMap<String, SourceFileMeta> {
+    let (included, excludes, slow_re) = match &source.files {
+        None => return files,
+        Some(config::SourceFiles::Explicit(included)) => (Some(included.to_owned()), vec![], None),
+        Some(config::SourceFiles::Filtered(config::SourceFilesFilter { slow_re, excludes })) => {
+            let slow_re = slow_re
+                .as_ref()
+                .and_then(|slow_re| match regex::Regex::new(slow_re) {
+                    Ok(slow_re) => Some(slow_re),
+                    Err(e) => {
+                        log::error!(
+                            "Could not parse slow-re \"{}\" of source folder {}. Error: {}",
+                            slow_re,
+                            source.dir,
+                            e
+                        );
+                        None
+                    }
+                });
+            (None, excludes.to_owned().unwrap_or_default(), slow_re)
+        }
+    };
+
+    files
+        .into_iter()
+        .filter(|(path, _)| {
+            let path = Path::new(path);
+            let relative_path = path.strip_prefix(&source.dir).unwrap_or(path);
+            let name = relative_path
+                .file_name()
+                .map(|name| name.to_string_lossy().to_string())
+                .unwrap_or_default();
+
+            let is_included = match &included {
+                Some(included) => included
+                    .iter()
+                    .any(|file| matches_source_file(relative_path, file)),
+                None => slow_re.as_ref().map(|re| re.is_match(&name)).unwrap_or(true),
+            };
+            let is_excluded = excludes
+                .iter()
+                .any(|file| matches_source_file(relative_path, file));
+
+            if !is_included || is_excluded {
+                log::info!("Excluded: {:?}", path);
+            }
+            is_included && !is_excluded
+        })
+        .collect()
+}
+
+/// The outputs of generators might not exist yet when we scan the source folders (the generators
+/// run right before parsing), but they still need to be part of the source files of the package so
+/// that they end up in the module graph.
+fn get_generated_source_files(
+    filter: &Option<regex::Regex>,
+    package_dir: &Path,
+    source: &config::PackageSource,
+) -> AHashMap<String, SourceFileMeta> {
+    source
+        .generators
+        .to_owned()
+        .unwrap_or_default()
+        .iter()
+        .flat_map(|generator| generator.get_outputs())
+        .map(|output| Path::new(&source.dir).join(output))
+        .filter(|path| {
+            path.extension()
+                .and_then(|extension| extension.to_str())
+                .map(helpers::is_source_file)
+                .unwrap_or(false)
+        })
+        .filter(|path| {
+            path.file_name()
+                .map(|name| matches_filter(filter, &name.to_string_lossy()))
+                .unwrap_or(false)
+        })
+        .map(|path| {
+            let modified = fs::metadata(package_dir.join(&path))
+                .and_then(|metadata| metadata.modified())
+                .unwrap_or(SystemTime::now());
+            (
+                path.to_string_lossy().to_string(),
+                SourceFileMeta {
+                    modified,
+                    is_type_dev: false,
+                },
+            )
+        })
+        .collect()
+}
+
 /// This takes the tree of packages, and finds all the source files for each, adding them to the
 /// respective packages.
 fn extend_with_children(
@@ -536,21 +701,41 @@ fn extend_with_children(
 ) -> AHashMap<String, Package> {
     for (_key, package) in build.iter_mut() {
         let mut map: AHashMap<String, SourceFileMeta> = AHashMap::new();
+        let mut private_modules: AHashSet<String> = AHashSet::new();
         package
             .source_folders
             .par_iter()
             .map(|source| {
-                get_source_files(
-                    &package.name,
-                    Path::new(&package.path),
-                    filter,
+                (
                     source,
-                    build_dev_deps,
+                    get_source_files(
+                        &package.name,
+                        &package.config,
+                        Path::new(&package.path),
+                        filter,
+                        source,
+                        build_dev_deps,
+                    ),
                 )
             })
-            .collect::<Vec<AHashMap<String, SourceFileMeta>>>()
+            .collect::<Vec<(&config::PackageSource, AHashMap<String, SourceFileMeta>)>>()
             .into_iter()
-            .for_each(|source| map.extend(source));
+            .for_each(|(source, source_files)| {
+                if let Some(public) = &source.public {
+                    private_modules.extend(
+                        source_files
+                            .keys()
+                            .filter(|path| {
+                                !public.is_public(&helpers::file_path_to_module_name(
+                                    path,
+                                    &Namespace::NoNamespace,
+                                ))
+                            })
+                            .map(|path| helpers::file_path_to_module_name(path, &package.namespace)),
+                    );
+                }
+                map.extend(source_files)
+            });
 
         let mut modules = AHashSet::from_iter(
             map.keys()
@@ -572,11 +757,154 @@ fn extend_with_children(
             dirs.insert(dir.to_owned());
         });
         package.dirs = Some(dirs);
+        package.private_modules = private_modules;
         package.source_files = Some(map);
     }
     build
 }
 
+// the dependencies of a package that are part of the build, with the field of the config that
+// declares them
+fn get_package_dependencies(package: &Package, build_dev_deps: bool) -> Vec<(String, &'static str)> {
+    let pinned_dependencies = package.config.pinned_dependencies.to_owned().unwrap_or_default();
+    let dependencies = package
+        .config
+        .bs_dependencies
+        .to_owned()
+        .unwrap_or_default()
+        .into_iter()
+        .map(|dependency| {
+            if pinned_dependencies.contains(&dependency) {
+                (dependency, "pinned-dependencies")
+            } else {
+                (dependency, "bs-dependencies")
+            }
+        });
+    let dev_dependencies = if build_dev_deps {
+        package.config.bs_dev_dependencies.to_owned().unwrap_or_default()
+    } else {
+        vec![]
+    }
+    .into_iter()
+    .map(|dependency| (dependency, "bs-dev-dependencies"));
+
+    dependencies.chain(dev_dependencies).collect()
+}
+
+fn visit_package(
+    package_name: &str,
+    packages: &AHashMap<String, Package>,
+    build_dev_deps: bool,
+    path: &mut Vec<(String, Option<&'static str>)>,
+    visited: &mut AHashSet<String>,
+) -> Option<Vec<(String, Option<&'static str>)>> {
+    let package = packages.get(package_name)?;
+    let mut dependencies = get_package_dependencies(package, build_dev_deps);
+    dependencies.sort();
+    for (dependency, dependency_type) in dependencies {
+        if let Some(index) = path.iter().position(|(name, _)| name == &dependency) {
+            let mut cycle = path[index..].to_vec();
+            cycle.push((dependency, Some(dependency_type)));
+            return Some(cycle);
+        }
+        if !visited.contains(&dependency) && packages.contains_key(&dependency) {
+            path.push((dependency.to_owned(), Some(dependency_type)));
+            if let Some(cycle) = visit_package(&dependency, packages, build_dev_deps, path, visited) {
+                return Some(cycle);
+            }
+            path.pop();
+        }
+    }
+    visited.insert(package_name.to_string());
+    None
+}
+
+/// Returns an error when the packages depend on each other in a cycle. A package that has a dev
+/// dependency on a package that depends on it (for instance test utilities) is fine, as long as the
+/// modules don't depend on each other in a cycle, so such a cycle is only a warning.
+fn check_package_cycles(packages: &AHashMap<String, Package>, build_dev_deps: bool) -> Result<()> {
+    // reading the dependencies stops at packages that are already read, so a cycle would only
+    // show up later as confusing errors about modules
+    if let Some(cycle) = find_package_cycle(packages, false) {
+        return Err(anyhow!(
+            "Found a circular dependency between packages:\n{}",
+            format_package_cycle(&cycle)
+        ));
+    }
+    if build_dev_deps {
+        if let Some(cycle) = find_package_cycle(packages, true) {
+            log::warn!(
+                "Found a circular dependency between packages through bs-dev-dependencies:\n{}",
+                format_package_cycle(&cycle)
+            );
+        }
+    }
+    Ok(())
+}
+
+/// Finds a cycle in the dependencies between the packages. The cycle is the chain of packages,
+/// with the type of dependency (bs-dependencies, pinned-dependencies or bs-dev-dependencies) on
+/// each of them, and ends with the package it starts with.
+pub fn find_package_cycle(
+    packages: &AHashMap<String, Package>,
+    build_dev_deps: bool,
+) -> Option<Vec<(String, Option<&'static str>)>> {
+    // start at the root package, so the cycle reads from the point of view of the project
+    let mut package_names = packages.keys().collect::<Vec<&String>>();
+    package_names.sort_by_key(|package_name| (!packages[*package_name].is_root, *package_name));
+    let mut visited = AHashSet::new();
+    package_names.into_iter().find_map(|package_name| {
+        let mut path = vec![(package_name.to_owned(), None)];
+        visit_package(package_name, packages, build_dev_deps, &mut path, &mut visited)
+    })
+}
+
+pub fn format_package_cycle(cycle: &[(String, Option<&'static str>)]) -> String {
+    cycle
+        .iter()
+        .map(|(package_name, dependency_type)| match dependency_type {
+            Some(dependency_type) => format!(" → {} ({})", package_name, dependency_type),
+            None => package_name.to_string(),
+        })
+        .collect::<Vec<String>>()
+        .join("\n")
+}
+
+/// The given packages together with all the packages they depend on, transitively. Returns an
+/// error when one of the given packages is not part of the build.
+pub fn get_dependency_closure(
+    packages: &AHashMap<String, Package>,
+    package_names: &[String],
+    build_dev_deps: bool,
+) -> Result<AHashSet<String>, String> {
+    let mut closure = AHashSet::new();
+    let mut queue = package_names.to_vec();
+    while let Some(package_name) = queue.pop() {
+        let package = match packages.get(&package_name) {
+            Some(package) => package,
+            None => {
+                let mut names = packages.keys().cloned().collect::<Vec<String>>();
+                names.sort();
+                return Err(format!(
+                    "Could not find package \"{}\". The packages in this build are: {}",
+                    package_name,
+                    names.join(", ")
+                ));
+            }
+        };
+        if closure.insert(package_name) {
+            queue.extend(
+                get_package_dependencies(package, build_dev_deps)
+                    .into_iter()
+                    .map(|(dependency, _)| dependency)
+                    // dependencies that are missing are reported when we validate the packages
+                    .filter(|dependency| packages.contains_key(dependency)),
+            );
+        }
+    }
+    Ok(closure)
+}
+
 /// Make turns a folder, that should contain a config, into a tree of Packages.
 /// It does so in two steps:
 /// 1. Get all the packages parsed, and take all the source folders from the config
@@ -591,7 +919,14 @@ pub fn make(
     show_progress: bool,
     build_dev_deps: bool,
 ) -> Result<AHashMap<String, Package>> {
-    let map = read_packages(root_folder, workspace_root.to_owned(), show_progress)?;
+    let map = read_packages(
+        root_folder,
+        workspace_root.to_owned(),
+        show_progress,
+        build_dev_deps,
+    )?;
+
+    check_package_cycles(&map, build_dev_deps)?;
 
     /* Once we have the deduplicated packages, we can add the source files for each - to minimize
      * the IO */
@@ -712,7 +1047,9 @@ pub fn parse_packages(build_state: &mut BuildState) {
                         deps,
                         dependents: AHashSet::new(),
                         package_name: package.name.to_owned(),
+                        is_type_dev: false,
                         compile_dirty: false,
+                        dirty_reason: None,
                         last_compiled_cmt: None,
                         last_compiled_cmi: None,
                     },
@@ -746,6 +1083,7 @@ pub fn parse_packages(build_state: &mut BuildState) {
                                     source_file.implementation.last_modified = metadata.modified;
                                     source_file.implementation.parse_dirty = true;
                                 }
+                                module.is_type_dev = metadata.is_type_dev;
                             })
                             .or_insert(Module {
                                 deps_dirty: true,
@@ -762,7 +1100,9 @@ pub fn parse_packages(build_state: &mut BuildState) {
                                 deps: AHashSet::new(),
                                 dependents: AHashSet::new(),
                                 package_name: package.name.to_owned(),
+                                is_type_dev: metadata.is_type_dev,
                                 compile_dirty: true,
+                                dirty_reason: Some(DirtyReason::NotCompiled),
                                 last_compiled_cmt: None,
                                 last_compiled_cmi: None,
                             });
@@ -817,7 +1157,9 @@ pub fn parse_packages(build_state: &mut BuildState) {
                                         deps: AHashSet::new(),
                                         dependents: AHashSet::new(),
                                         package_name: package.name.to_owned(),
+                                        is_type_dev: metadata.is_type_dev,
                                         compile_dirty: true,
+                                        dirty_reason: Some(DirtyReason::NotCompiled),
                                         last_compiled_cmt: None,
                                         last_compiled_cmi: None,
                                     });
@@ -943,10 +1285,11 @@ pub fn validate_packages_dependencies(packages: &AHashMap<String, Package>) -> b
 
 #[cfg(test)]
 mod test {
-    use crate::config::Source;
+    use crate::config::{PackageSource, Source, SourceFiles, SourceFilesFilter, Subdirs};
     use ahash::{AHashMap, AHashSet};
+    use std::time::SystemTime;
 
-    use super::{Namespace, Package};
+    use super::{Namespace, Package, SourceFileMeta};
 
     fn create_package(
         name: String,
@@ -962,6 +1305,8 @@ mod test {
                 sources: Some(crate::config::OneOrMore::Single(Source::Shorthand(String::from(
                     "Source",
                 )))),
+                ignored_dirs: None,
+                generators: None,
                 package_specs: None,
                 warnings: None,
                 suffix: None,
@@ -975,6 +1320,8 @@ mod test {
                 jsx: None,
                 uncurried: None,
                 gentype_config: None,
+                js_post_build: None,
+                entries: None,
                 namespace_entry: None,
                 allowed_dependents,
             },
@@ -984,11 +1331,84 @@ mod test {
             modules: None,
             path: String::from("./something"),
             dirs: None,
+            private_modules: AHashSet::new(),
             is_pinned_dep: false,
             is_root: false,
             is_local_dep: false,
         }
     }
+    fn create_source_files(paths: Vec<&str>) -> AHashMap<String, SourceFileMeta> {
+        paths
+            .into_iter()
+            .map(|path| {
+                (
+                    path.to_string(),
+                    SourceFileMeta {
+                        modified: SystemTime::now(),
+                        is_type_dev: false,
+                    },
+                )
+            })
+            .collect()
+    }
+
+    fn create_source(files: Option<SourceFiles>) -> PackageSource {
+        PackageSource {
+            dir: String::from("src"),
+            subdirs: Some(Subdirs::Recurse(true)),
+            type_: None,
+            generators: None,
+            files,
+            public: None,
+        }
+    }
+
+    fn sorted_keys(files: AHashMap<String, SourceFileMeta>) -> Vec<String> {
+        let mut keys = files.into_keys().collect::<Vec<String>>();
+        keys.sort();
+        keys
+    }
+
+    #[test]
+    fn should_only_include_explicit_source_files() {
+        let source = create_source(Some(SourceFiles::Explicit(vec![
+            String::from("App.res"),
+            String::from("nested/Other.res"),
+        ])));
+        let files = create_source_files(vec![
+            "src/App.res",
+            "src/Scratch.res",
+            "src/nested/App.res",
+            "src/nested/Other.res",
+            "src/other/Other.res",
+        ]);
+
+        assert_eq!(
+            sorted_keys(super::filter_source_files(&source, files)),
+            vec!["src/App.res", "src/nested/Other.res"]
+        );
+    }
+
+    #[test]
+    fn should_exclude_source_files() {
+        let source = create_source(Some(SourceFiles::Filtered(SourceFilesFilter {
+            slow_re: Some(String::from("^[A-Z].*\\.res$")),
+            excludes: Some(vec![String::from("Scratch.res")]),
+        })));
+        let files = create_source_files(vec![
+            "src/App.res",
+            "src/App.resi",
+            "src/Scratch.res",
+            "src/nested/Scratch.res",
+            "src/lower.res",
+        ]);
+
+        assert_eq!(
+            sorted_keys(super::filter_source_files(&source, files)),
+            vec!["src/App.res", "src/nested/Scratch.res"]
+        );
+    }
+
     #[test]
     fn should_return_false_with_invalid_parents_as_bs_dependencies() {
         let mut packages: AHashMap<String, Package> = AHashMap::new();
@@ -1100,4 +1520,268 @@ mod test {
         let is_valid = super::validate_packages_dependencies(&packages);
         assert!(is_valid)
     }
+
+    #[test]
+    fn should_find_a_package_cycle() {
+        let mut packages: AHashMap<String, Package> = AHashMap::new();
+        packages.insert(
+            "Package1".to_string(),
+            create_package(
+                "Package1".to_string(),
+                vec!["Package2".to_string()],
+                vec![],
+                vec![],
+                None,
+            ),
+        );
+        packages.insert(
+            "Package2".to_string(),
+            create_package(
+                "Package2".to_string(),
+                vec!["Package3".to_string()],
+                vec!["Package3".to_string()],
+                vec![],
+                None,
+            ),
+        );
+        packages.insert(
+            "Package3".to_string(),
+            create_package(
+                "Package3".to_string(),
+                vec![],
+                vec![],
+                vec!["Package1".to_string()],
+                None,
+            ),
+        );
+
+        assert_eq!(super::find_package_cycle(&packages, false), None);
+        let cycle = super::find_package_cycle(&packages, true).expect("Expected a cycle");
+        assert_eq!(
+            super::format_package_cycle(&cycle),
+            "Package1\n → Package2 (bs-dependencies)\n → Package3 (pinned-dependencies)\n → Package1 (bs-dev-dependencies)"
+        );
+    }
+
+    #[test]
+    fn should_only_warn_about_a_package_cycle_through_dev_dependencies() {
+        let mut packages: AHashMap<String, Package> = AHashMap::new();
+        packages.insert(
+            "App".to_string(),
+            create_package(
+                "App".to_string(),
+                vec![],
+                vec![],
+                vec!["Tester".to_string()],
+                None,
+            ),
+        );
+        packages.insert(
+            "Tester".to_string(),
+            create_package(
+                "Tester".to_string(),
+                vec!["App".to_string()],
+                vec![],
+                vec![],
+                None,
+            ),
+        );
+
+        assert_eq!(super::find_package_cycle(&packages, false), None);
+        // this cycle is only a warning
+        assert!(super::check_package_cycles(&packages, true).is_ok());
+        let cycle = super::find_package_cycle(&packages, true).expect("Expected a cycle");
+        assert_eq!(
+            super::format_package_cycle(&cycle),
+            "App\n → Tester (bs-dev-dependencies)\n → App (bs-dependencies)"
+        );
+
+        packages.insert(
+            "App".to_string(),
+            create_package(
+                "App".to_string(),
+                vec!["Tester".to_string()],
+                vec![],
+                vec![],
+                None,
+            ),
+        );
+        assert!(super::check_package_cycles(&packages, false).is_err());
+    }
+
+    #[test]
+    fn should_get_the_dependency_closure() {
+        let mut packages: AHashMap<String, Package> = AHashMap::new();
+        packages.insert(
+            "App".to_string(),
+            create_package("App".to_string(), vec!["Ui".to_string()], vec![], vec![], None),
+        );
+        packages.insert(
+            "Ui".to_string(),
+            create_package(
+                "Ui".to_string(),
+                vec!["Utils".to_string()],
+                vec![],
+                vec!["TestUtils".to_string()],
+                None,
+            ),
+        );
+        packages.insert(
+            "Utils".to_string(),
+            create_package("Utils".to_string(), vec![], vec![], vec![], None),
+        );
+        packages.insert(
+            "TestUtils".to_string(),
+            create_package("TestUtils".to_string(), vec![], vec![], vec![], None),
+        );
+        packages.insert(
+            "Admin".to_string(),
+            create_package(
+                "Admin".to_string(),
+                vec!["Utils".to_string()],
+                vec![],
+                vec![],
+                None,
+            ),
+        );
+
+        let sorted = |closure: AHashSet<String>| {
+            let mut closure = closure.into_iter().collect::<Vec<String>>();
+            closure.sort();
+            closure
+        };
+        assert_eq!(
+            sorted(super::get_dependency_closure(&packages, &["App".to_string()], false).unwrap()),
+            vec!["App", "Ui", "Utils"]
+        );
+        assert_eq!(
+            sorted(super::get_dependency_closure(&packages, &["App".to_string()], true).unwrap()),
+            vec!["App", "TestUtils", "Ui", "Utils"]
+        );
+        assert!(super::get_dependency_closure(&packages, &["Missing".to_string()], false).is_err());
+    }
+
+    fn write_package(path: &std::path::Path, name: &str, config: &str) {
+        std::fs::create_dir_all(path).unwrap();
+        std::fs::write(path.join("package.json"), format!(r#"{{ "name": "{}" }}"#, name)).unwrap();
+        std::fs::write(path.join("rescript.json"), config).unwrap();
+    }
+
+    #[test]
+    fn should_read_the_dev_dependencies_of_local_packages_with_dev() {
+        let root = std::env::temp_dir().join(format!("rewatch-dev-dependencies-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&root);
+        write_package(
+            &root,
+            "root",
+            r#"{ "name": "root", "bs-dependencies": ["dep"], "bs-dev-dependencies": ["tester"] }"#,
+        );
+        // the dev dependencies of a package from node_modules are usually not installed
+        write_package(
+            &root.join("node_modules/dep"),
+            "dep",
+            r#"{ "name": "dep", "sources": "src", "bs-dev-dependencies": ["missing"] }"#,
+        );
+        write_package(
+            &root.join("node_modules/tester"),
+            "tester",
+            r#"{ "name": "tester", "sources": "src" }"#,
+        );
+        let root = root.canonicalize().unwrap().to_string_lossy().to_string();
+
+        let sorted = |packages: AHashMap<String, Package>| {
+            let mut names = packages.into_keys().collect::<Vec<String>>();
+            names.sort();
+            names
+        };
+        assert_eq!(
+            sorted(super::read_packages(&root, None, false, false).unwrap()),
+            vec!["dep", "root"]
+        );
+        assert_eq!(
+            sorted(super::read_packages(&root, None, false, true).unwrap()),
+            vec!["dep", "root", "tester"]
+        );
+        let _ = std::fs::remove_dir_all(&root);
+    }
+
+    #[test]
+    fn should_only_include_dev_source_folders_with_dev() {
+        let root = std::env::temp_dir().join(format!("rewatch-dev-sources-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&root);
+        write_package(
+            &root,
+            "root",
+            r#"{ "name": "root", "sources": "src", "bs-dependencies": ["dep"] }"#,
+        );
+        // a local package, linked into node_modules
+        write_package(
+            &root.join("packages/dep"),
+            "dep",
+            r#"{ "name": "dep", "sources": ["src", { "dir": "test", "type": "dev" }] }"#,
+        );
+        std::fs::create_dir_all(root.join("node_modules")).unwrap();
+        std::os::unix::fs::symlink(root.join("packages/dep"), root.join("node_modules/dep")).unwrap();
+        for path in [
+            "src/App.res",
+            "packages/dep/src/Dep.res",
+            "packages/dep/test/DepTest.res",
+        ] {
+            std::fs::create_dir_all(root.join(path).parent().unwrap()).unwrap();
+            std::fs::write(root.join(path), "").unwrap();
+        }
+        for path in ["lib/bs", "packages/dep/lib/bs"] {
+            std::fs::create_dir_all(root.join(path)).unwrap();
+        }
+        let root = root.canonicalize().unwrap().to_string_lossy().to_string();
+
+        let get_source_dirs = |build_dev_deps: bool| {
+            let packages = super::make(&None, &root, &None, false, build_dev_deps).unwrap();
+            let mut source_files = packages["dep"]
+                .source_files
+                .as_ref()
+                .unwrap()
+                .keys()
+                .cloned()
+                .collect::<Vec<String>>();
+            source_files.sort();
+            crate::sourcedirs::print(&crate::build::build_types::BuildState::new(
+                root.to_owned(),
+                "root".to_string(),
+                packages,
+                None,
+                "".to_string(),
+                "".to_string(),
+            ));
+            let source_dirs = serde_json::from_str::<serde_json::Value>(
+                &std::fs::read_to_string(std::path::Path::new(&root).join("lib/bs/.sourcedirs.json"))
+                    .unwrap(),
+            )
+            .unwrap();
+            let mut dirs = source_dirs["dirs"]
+                .as_array()
+                .unwrap()
+                .iter()
+                .map(|dir| dir.as_str().unwrap().to_string())
+                .collect::<Vec<String>>();
+            dirs.sort();
+            (source_files, dirs)
+        };
+
+        assert_eq!(
+            get_source_dirs(false),
+            (
+                vec!["src/Dep.res".to_string()],
+                vec!["packages/dep/src".to_string()]
+            )
+        );
+        assert_eq!(
+            get_source_dirs(true),
+            (
+                vec!["src/Dep.res".to_string(), "test/DepTest.res".to_string()],
+                vec!["packages/dep/src".to_string(), "packages/dep/test".to_string()]
+            )
+        );
+        let _ = std::fs::remove_dir_all(&root);
+    }
 }
@@ -1,4 +1,6 @@
+use super::artifact_cache::{self, ArtifactCache};
 use super::build_types::*;
+use super::diagnostics;
 use super::logs;
 use super::namespaces;
 use super::packages;
@@ -10,6 +12,7 @@ use log::debug;
 use rayon::prelude::*;
 use std::path::{Path, PathBuf};
 use std::process::Command;
+use std::time::Instant;
 
 pub fn generate_asts(
     build_state: &mut BuildState,
@@ -17,6 +20,7 @@ pub fn generate_asts(
 ) -> Result<String, String> {
     let mut has_failure = false;
     let mut stderr = "".to_string();
+    let mut parse_diagnostics = vec![];
 
     build_state
         .modules
@@ -48,6 +52,7 @@ pub fn generate_asts(
                             .unwrap_or(false)
                     {
                         inc();
+                        let timing_parse = Instant::now();
                         let ast_result = generate_ast(
                             package.to_owned(),
                             root_package.to_owned(),
@@ -55,6 +60,7 @@ pub fn generate_asts(
                             &build_state.rescript_version,
                             &build_state.bsc_path,
                             &build_state.workspace_root,
+                            &build_state.artifact_cache,
                         );
 
                         let iast_result = match source_file.interface.as_ref().map(|i| i.path.to_owned()) {
@@ -65,10 +71,19 @@ pub fn generate_asts(
                                 &build_state.rescript_version,
                                 &build_state.bsc_path,
                                 &build_state.workspace_root,
+                                &build_state.artifact_cache,
                             )
                             .map(Some),
                             _ => Ok(None),
                         };
+                        if let Some(profile) = &build_state.profile {
+                            profile.record(
+                                &helpers::format_namespaced_module_name(module_name),
+                                "parse",
+                                timing_parse,
+                                timing_parse.elapsed(),
+                            );
+                        }
 
                         (ast_result, iast_result, true)
                     } else {
@@ -109,7 +124,7 @@ pub fn generate_asts(
                 // do NOT set to false if the module is not parse_dirty, it needs to keep
                 // the compile_dirty flag if it was set before
                 if is_dirty {
-                    module.compile_dirty = true;
+                    module.mark_compile_dirty(DirtyReason::SourceChanged);
                     module.deps_dirty = true;
                 }
                 let package = build_state
@@ -127,6 +142,11 @@ pub fn generate_asts(
                             source_file.implementation.parse_state = ParseState::Warning;
                             source_file.implementation.parse_dirty = true;
                             logs::append(package, &stderr_warnings);
+                            parse_diagnostics.extend(diagnostics::parse(
+                                &stderr_warnings,
+                                &package.name,
+                                &module_name,
+                            ));
                             stderr.push_str(&stderr_warnings);
                         }
                         Ok((_path, Some(_))) | Ok((_path, None)) => {
@@ -140,6 +160,7 @@ pub fn generate_asts(
                             source_file.implementation.parse_state = ParseState::ParseError;
                             source_file.implementation.parse_dirty = true;
                             logs::append(package, &err);
+                            parse_diagnostics.extend(diagnostics::parse(&err, &package.name, &module_name));
                             has_failure = true;
                             stderr.push_str(&err);
                         }
@@ -157,6 +178,11 @@ pub fn generate_asts(
                                 interface.parse_dirty = true;
                             }
                             logs::append(package, &stderr_warnings);
+                            parse_diagnostics.extend(diagnostics::parse(
+                                &stderr_warnings,
+                                &package.name,
+                                &module_name,
+                            ));
                             stderr.push_str(&stderr_warnings);
                         }
                         Ok(Some((_, None))) | Ok(Some((_, Some(_)))) => {
@@ -174,6 +200,7 @@ pub fn generate_asts(
                                 interface.parse_dirty = true;
                             }
                             logs::append(package, &err);
+                            parse_diagnostics.extend(diagnostics::parse(&err, &package.name, &module_name));
                             has_failure = true;
                             stderr.push_str(&err);
                         }
@@ -185,6 +212,8 @@ pub fn generate_asts(
             }
         });
 
+    build_state.diagnostics.extend(parse_diagnostics);
+
     // compile the mlmaps of dirty modules
     // first collect dirty packages
     let dirty_packages = build_state
@@ -242,7 +271,7 @@ pub fn generate_asts(
             _ => false,
         };
         if is_dirty {
-            module.compile_dirty = is_dirty;
+            module.mark_compile_dirty(DirtyReason::SourceChanged);
         }
     });
 
@@ -309,6 +338,7 @@ fn generate_ast(
     version: &str,
     bsc_path: &str,
     workspace_root: &Option<String>,
+    artifact_cache: &Option<ArtifactCache>,
 ) -> Result<(PathBuf, Option<helpers::StdErr>), String> {
     let file_path = PathBuf::from(&package.path).join(filename);
     let contents = helpers::read_file(&file_path).expect("Error reading file");
@@ -327,8 +357,28 @@ fn generate_ast(
     // generate the dir of the ast_path (it mirrors the source file dir)
     helpers::create_path(&(package.get_build_path() + "/" + &ast_path.parent().unwrap().to_string_lossy()));
 
+    // a ppx is an input of the parser that we don't hash, so the asts of files that are
+    // transformed by a ppx are not cached
+    let artifact_cache = artifact_cache
+        .as_ref()
+        .filter(|_| !parser_args.iter().any(|arg| arg == "-ppx"))
+        .map(|cache| {
+            let key = artifact_cache::get_parse_key(version, &parser_args, &file_path);
+            (cache, key, vec![Path::new(&build_path_abs).join(&ast_path)])
+        });
+    let restored_stderr = artifact_cache
+        .as_ref()
+        .and_then(|(cache, key, outputs)| cache.restore(key, outputs));
+
     /* Create .ast */
-    let result = if let Some(res_to_ast) = Some(
+    let result = if let Some(stderr) = restored_stderr {
+        // only successful parses are cached
+        if helpers::contains_ascii_characters(&stderr) {
+            Ok((ast_path, Some(stderr)))
+        } else {
+            Ok((ast_path, None))
+        }
+    } else if let Some(res_to_ast) = Some(
         Command::new(bsc_path)
             .current_dir(&build_path_abs)
             .args(parser_args)
@@ -336,6 +386,11 @@ fn generate_ast(
             .expect("Error converting .res to .ast"),
     ) {
         let stderr = String::from_utf8_lossy(&res_to_ast.stderr).to_string();
+        if let Some((cache, key, outputs)) = &artifact_cache {
+            if res_to_ast.status.success() {
+                cache.store(key, outputs, &stderr);
+            }
+        }
 
         if helpers::contains_ascii_characters(&stderr) {
             if res_to_ast.status.success() {
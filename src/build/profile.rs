@@ -0,0 +1,125 @@
+use serde_json::json;
+use std::fs::File;
+use std::io::Write;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+// Records how long the phases of a build, and the parsing and compiling of every module take, and
+// on which thread. The spans are written in the Chrome trace event format, which can be opened in
+// Perfetto (https://ui.perfetto.dev) or chrome://tracing to find the critical path of a build and
+// the workers that are idle.
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct Span {
+    name: String,
+    category: &'static str,
+    start: Duration,
+    duration: Duration,
+    // 0 is the main thread, the workers of rayon start at 1
+    thread: usize,
+}
+
+#[derive(Debug)]
+pub struct Profile {
+    start: Instant,
+    spans: Mutex<Vec<Span>>,
+}
+
+impl Profile {
+    pub fn new(start: Instant) -> Self {
+        Self {
+            start,
+            spans: Mutex::new(vec![]),
+        }
+    }
+
+    /// Records a span on the current thread. This can be called from the workers of rayon.
+    pub fn record(&self, name: &str, category: &'static str, start: Instant, duration: Duration) {
+        let span = Span {
+            name: name.to_string(),
+            category,
+            start: start.saturating_duration_since(self.start),
+            duration,
+            thread: rayon::current_thread_index().map_or(0, |index| index + 1),
+        };
+        self.spans.lock().unwrap().push(span);
+    }
+
+    pub fn to_json(&self) -> serde_json::Value {
+        let mut spans = self.spans.lock().unwrap().to_owned();
+        spans.sort_by_key(|span| (span.start, span.thread));
+        let mut threads = spans.iter().map(|span| span.thread).collect::<Vec<usize>>();
+        threads.sort();
+        threads.dedup();
+
+        let thread_names = threads.into_iter().map(|thread| {
+            json!({
+                "name": "thread_name",
+                "ph": "M",
+                "pid": 1,
+                "tid": thread,
+                "args": {
+                    "name": if thread == 0 { "main".to_string() } else { format!("worker {}", thread) }
+                },
+            })
+        });
+        let events = spans.iter().map(|span| {
+            json!({
+                "name": span.name,
+                "cat": span.category,
+                "ph": "X",
+                "ts": span.start.as_micros() as u64,
+                "dur": span.duration.as_micros() as u64,
+                "pid": 1,
+                "tid": span.thread,
+            })
+        });
+
+        json!({
+            "traceEvents": thread_names.chain(events).collect::<Vec<serde_json::Value>>(),
+            "displayTimeUnit": "ms",
+        })
+    }
+}
+
+pub fn write(profile: &Profile, path: &str) {
+    let mut profile_file = File::create(path).expect("Could not create profile file");
+    profile_file
+        .write_all(profile.to_json().to_string().as_bytes())
+        .expect("Could not write profile file");
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_to_json() {
+        let start = Instant::now();
+        let profile = Profile::new(start);
+        profile.record(
+            "Compile",
+            "phase",
+            start + Duration::from_millis(3),
+            Duration::from_millis(2),
+        );
+        profile.record(
+            "A",
+            "parse",
+            start + Duration::from_micros(10),
+            Duration::from_micros(5),
+        );
+
+        assert_eq!(
+            profile.to_json(),
+            json!({
+                "traceEvents": [
+                    {"name": "thread_name", "ph": "M", "pid": 1, "tid": 0, "args": {"name": "main"}},
+                    {"name": "A", "cat": "parse", "ph": "X", "ts": 10, "dur": 5, "pid": 1, "tid": 0},
+                    {"name": "Compile", "cat": "phase", "ph": "X", "ts": 3000, "dur": 2000, "pid": 1, "tid": 0},
+                ],
+                "displayTimeUnit": "ms",
+            })
+        );
+    }
+}
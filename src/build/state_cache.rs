@@ -0,0 +1,461 @@
+use super::build_types::*;
+use super::packages;
+use crate::helpers;
+use log::debug;
+use rayon::prelude::*;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::Path;
+use std::time::SystemTime;
+
+// After a build we write the state of all modules (their deps, and the metadata and hashes of
+// their source files and .cmi files) to the build folder of the root package. Modules that failed
+// to parse or compile, or were not compiled yet, are stored as dirty. On the
+// next run we use it instead of scanning all the compiler assets and reading all the ASTs again,
+// which is the bulk of the work of a build without changes. Only the source files of which the
+// modification time or the size changed need to be hashed, and only the files of which the
+// content changed need to be parsed (and compiled) again.
+//
+// The full state is only restored for the exact same set of modules, and when the compiler
+// assets of all modules have the same modification times as right after the build, so they were
+// not touched by something else than rewatch (for instance bsb). Otherwise we read the compile state
+// from the compiler assets, and we only use the hashes to find out which source files really
+// changed (the modification times can't be trusted after a `git checkout`, or when the build
+// folders are restored from a cache in CI).
+//
+// If the compiler or any of the configs changed, the state is not used at all.
+
+static STATE_FILE: &str = ".rewatch-state";
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+struct CachedFile {
+    path: String,
+    modified: SystemTime,
+    size: u64,
+    hash: Option<String>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+struct CachedModule {
+    package_name: String,
+    implementation: CachedFile,
+    interface: Option<CachedFile>,
+    deps: Vec<String>,
+    // a module is dirty when it needs to be compiled again in the next build, for instance because
+    // it has warnings
+    dirty: bool,
+    #[serde(default)]
+    dirty_reason: Option<DirtyReason>,
+    cmi_hash: Option<String>,
+    last_compiled_cmi: Option<SystemTime>,
+    last_compiled_cmt: Option<SystemTime>,
+    // the modification times of the compiler assets (in lib/ocaml) by extension
+    assets: BTreeMap<String, Option<SystemTime>>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+struct CachedState {
+    version: String,
+    rescript_version: String,
+    bsc_path: String,
+    build_dev_deps: bool,
+    // the hashes of the config files of all packages
+    configs: BTreeMap<String, Option<String>>,
+    modules: BTreeMap<String, CachedModule>,
+}
+
+/// The state of the previous build
+pub struct PreviousBuild {
+    state: CachedState,
+}
+
+fn get_state_path(build_state: &BuildState) -> Option<String> {
+    build_state
+        .get_package(&build_state.root_config_name)
+        .map(|package| package.get_build_path() + "/" + STATE_FILE)
+}
+
+fn get_config_hash(package: &packages::Package) -> Option<String> {
+    let rescript_json = Path::new(&package.path).join("rescript.json");
+    let config_path = if rescript_json.exists() {
+        rescript_json
+    } else {
+        Path::new(&package.path).join("bsconfig.json")
+    };
+    get_hash(&config_path)
+}
+
+fn get_hash(path: &Path) -> Option<String> {
+    helpers::compute_file_hash(path).map(|hash| hash.to_hex().to_string())
+}
+
+fn get_config_hashes(build_state: &BuildState) -> BTreeMap<String, Option<String>> {
+    build_state
+        .packages
+        .values()
+        .map(|package| (package.name.to_owned(), get_config_hash(package)))
+        .collect()
+}
+
+fn get_assets(
+    package: &packages::Package,
+    implementation_path: &str,
+) -> BTreeMap<String, Option<SystemTime>> {
+    ["cmi", "cmj", "cmt"]
+        .iter()
+        .map(|extension| {
+            let path =
+                helpers::get_compiler_asset(package, &package.namespace, implementation_path, extension);
+            (
+                extension.to_string(),
+                fs::metadata(path).and_then(|metadata| metadata.modified()).ok(),
+            )
+        })
+        .collect()
+}
+
+fn has_same_assets(state: &CachedState, build_state: &BuildState) -> bool {
+    state.modules.par_iter().all(|(_, cached)| {
+        build_state
+            .get_package(&cached.package_name)
+            .is_some_and(|package| get_assets(package, &cached.implementation.path) == cached.assets)
+    })
+}
+
+fn get_cmi_hash(package: &packages::Package, implementation_path: &str) -> Option<String> {
+    get_hash(Path::new(&helpers::get_compiler_asset(
+        package,
+        &package.namespace,
+        implementation_path,
+        "cmi",
+    )))
+}
+
+// when the file was saved again after it was read for this build (for instance while the watcher
+// was compiling), its content is not the one that was compiled, so we don't store a hash and the
+// next build will see it as changed
+fn read_file(package: &packages::Package, path: &str, last_modified: SystemTime) -> Option<CachedFile> {
+    let full_path = Path::new(&package.path).join(path);
+    let metadata = fs::metadata(&full_path).ok()?;
+    let modified = metadata.modified().ok()?;
+    Some(CachedFile {
+        path: path.to_string(),
+        modified: last_modified,
+        size: metadata.len(),
+        hash: if modified == last_modified {
+            get_hash(&full_path)
+        } else {
+            None
+        },
+    })
+}
+
+// a file is unchanged when the modification time and size are the same as in the previous build.
+// Only when they differ, we compare the content. This way touching a file (or checking it out
+// again) doesn't cause a recompile
+fn is_file_unchanged(package: &packages::Package, path: &str, cached: &CachedFile) -> bool {
+    if cached.path != path {
+        return false;
+    }
+    let full_path = Path::new(&package.path).join(path);
+    match fs::metadata(&full_path) {
+        Ok(metadata)
+            if metadata.len() == cached.size && metadata.modified().ok() == Some(cached.modified) =>
+        {
+            true
+        }
+        Ok(_) => cached.hash.is_some() && get_hash(&full_path) == cached.hash,
+        Err(_) => false,
+    }
+}
+
+fn is_source_file_unchanged(
+    package: &packages::Package,
+    source_file: &SourceFile,
+    cached: &CachedModule,
+) -> bool {
+    is_file_unchanged(package, &source_file.implementation.path, &cached.implementation)
+        && match (&source_file.interface, &cached.interface) {
+            (Some(interface), Some(cached_interface)) => {
+                is_file_unchanged(package, &interface.path, cached_interface)
+            }
+            (None, None) => true,
+            _ => false,
+        }
+}
+
+fn is_module_dirty(module: &Module) -> bool {
+    module.compile_dirty
+        || module.deps_dirty
+        || match &module.source_type {
+            SourceType::SourceFile(source_file) => {
+                let has_problems = |parse_state: &ParseState, compile_state: &CompileState| {
+                    matches!(parse_state, ParseState::Warning | ParseState::ParseError)
+                        || matches!(compile_state, CompileState::Warning | CompileState::Error)
+                };
+                source_file.implementation.parse_dirty
+                    || has_problems(
+                        &source_file.implementation.parse_state,
+                        &source_file.implementation.compile_state,
+                    )
+                    || source_file.interface.as_ref().is_some_and(|interface| {
+                        interface.parse_dirty
+                            || has_problems(&interface.parse_state, &interface.compile_state)
+                    })
+            }
+            SourceType::MlMap(_) => false,
+        }
+}
+
+fn has_same_modules(state: &CachedState, build_state: &BuildState) -> bool {
+    let source_modules = build_state
+        .modules
+        .iter()
+        .filter_map(|(module_name, module)| match &module.source_type {
+            SourceType::SourceFile(source_file) => Some((module_name, module, source_file)),
+            SourceType::MlMap(_) => None,
+        })
+        .collect::<Vec<_>>();
+
+    source_modules.len() == state.modules.len()
+        && source_modules.iter().all(|(module_name, module, source_file)| {
+            state.modules.get(*module_name).is_some_and(|cached| {
+                cached.package_name == module.package_name
+                    && cached.implementation.path == source_file.implementation.path
+                    && cached.interface.as_ref().map(|interface| &interface.path)
+                        == source_file.interface.as_ref().map(|interface| &interface.path)
+            })
+        })
+}
+
+/// Reads the state of the previous build. Returns None when there is no state, or when it was
+/// written by a different compiler or with different configs.
+pub fn read(build_state: &BuildState, build_dev_deps: bool, read_only: bool) -> Option<PreviousBuild> {
+    let path = get_state_path(build_state)?;
+    let contents = fs::read_to_string(&path).ok();
+    // the state is only valid for the compiler assets it was written with, so we remove it as soon
+    // as we start building. It is written again after the build.
+    if !read_only {
+        let _ = fs::remove_file(&path);
+    }
+    let state = serde_json::from_str::<CachedState>(&contents?).ok()?;
+
+    if state.version != env!("CARGO_PKG_VERSION")
+        || state.rescript_version != build_state.rescript_version
+        || state.bsc_path != build_state.bsc_path
+        || state.build_dev_deps != build_dev_deps
+    {
+        debug!("Build state cache is from a different compiler or build");
+        return None;
+    }
+
+    if state.configs != get_config_hashes(build_state) {
+        debug!("Build state cache is invalid, configs changed");
+        return None;
+    }
+
+    Some(PreviousBuild { state })
+}
+
+/// Restores the state of the modules from the previous build. Returns the number of source files
+/// when the state could be restored, or None when we need to read the compile state from the
+/// compiler assets.
+pub fn restore(build_state: &mut BuildState, previous_build: &PreviousBuild) -> Option<usize> {
+    let state = &previous_build.state;
+    if !has_same_modules(state, build_state) {
+        debug!("Build state cache is invalid, source files were added or removed");
+        return None;
+    }
+    if !has_same_assets(state, build_state) {
+        debug!("Build state cache is invalid, compiler assets changed");
+        return None;
+    }
+
+    let mut num_source_files = 0;
+    for (module_name, cached) in state.modules.iter() {
+        let package = build_state
+            .packages
+            .get(&cached.package_name)
+            .expect("Package not found");
+        let module = build_state
+            .modules
+            .get_mut(module_name)
+            .expect("Module not found");
+
+        module.last_compiled_cmi = cached.last_compiled_cmi;
+        module.last_compiled_cmt = cached.last_compiled_cmt;
+
+        if let SourceType::SourceFile(ref mut source_file) = module.source_type {
+            num_source_files += 1 + source_file.interface.iter().count();
+
+            let is_unchanged = is_source_file_unchanged(package, source_file, cached);
+            if !cached.dirty && is_unchanged {
+                source_file.implementation.parse_dirty = false;
+                if let Some(interface) = source_file.interface.as_mut() {
+                    interface.parse_dirty = false;
+                }
+                module.mark_compile_clean();
+                module.deps = cached.deps.iter().cloned().collect();
+                module.deps_dirty = false;
+            } else if is_unchanged {
+                module.dirty_reason = cached.dirty_reason.to_owned().or(module.dirty_reason.to_owned());
+            } else {
+                module.dirty_reason = Some(DirtyReason::SourceChanged);
+            }
+        }
+    }
+    build_state.deps_initialized = true;
+
+    Some(num_source_files)
+}
+
+/// After reading the compile state from the compiler assets, the dirtiness of the modules is
+/// based on the modification times of the source files and the compiler assets. When we know the
+/// hashes of the source files from the previous build, we decide on their content instead.
+pub fn restore_hashes(build_state: &mut BuildState, previous_build: &PreviousBuild) {
+    let state = &previous_build.state;
+    let results = build_state
+        .modules
+        .par_iter()
+        .filter_map(|(module_name, module)| {
+            let cached = state.modules.get(module_name)?;
+            let package = build_state.get_package(&module.package_name)?;
+            match &module.source_type {
+                SourceType::SourceFile(source_file) if cached.package_name == module.package_name => {
+                    let has_asts = Path::new(&helpers::get_compiler_asset(
+                        package,
+                        &packages::Namespace::NoNamespace,
+                        &source_file.implementation.path,
+                        "ast",
+                    ))
+                    .exists()
+                        && !source_file.interface.as_ref().is_some_and(|interface| {
+                            !Path::new(&helpers::get_compiler_asset(
+                                package,
+                                &packages::Namespace::NoNamespace,
+                                &interface.path,
+                                "iast",
+                            ))
+                            .exists()
+                        });
+                    let is_unchanged = is_source_file_unchanged(package, source_file, cached);
+                    let is_same_cmi = cached.cmi_hash.is_some()
+                        && get_cmi_hash(package, &source_file.implementation.path) == cached.cmi_hash;
+                    Some((module_name.to_owned(), is_unchanged, has_asts, is_same_cmi))
+                }
+                _ => None,
+            }
+        })
+        .collect::<Vec<(String, bool, bool, bool)>>();
+
+    for (module_name, is_unchanged, has_asts, is_same_cmi) in results {
+        let cached = state.modules.get(&module_name).expect("Module not found");
+        let module = build_state
+            .modules
+            .get_mut(&module_name)
+            .expect("Module not found");
+        if let SourceType::SourceFile(ref mut source_file) = module.source_type {
+            if !is_unchanged {
+                // the compiler assets can be newer than the source file, while they were compiled
+                // from a different version of it
+                source_file.implementation.parse_dirty = true;
+                if let Some(interface) = source_file.interface.as_mut() {
+                    interface.parse_dirty = true;
+                }
+                module.compile_dirty = true;
+                module.dirty_reason = Some(DirtyReason::SourceChanged);
+            } else if has_asts && is_same_cmi && !cached.dirty {
+                source_file.implementation.parse_dirty = false;
+                if let Some(interface) = source_file.interface.as_mut() {
+                    interface.parse_dirty = false;
+                }
+                module.mark_compile_clean();
+                // the modification times of the compiler assets are not reliable, but the .cmi
+                // is the same as the one of the previous build, so we can use its compile times
+                module.last_compiled_cmi = cached.last_compiled_cmi;
+                module.last_compiled_cmt = cached.last_compiled_cmt;
+            }
+        }
+    }
+}
+
+/// Returns true when the content of the source file of the module is the same as in the previous
+/// build, even when it was saved again
+pub fn is_source_unchanged(
+    previous_build: &PreviousBuild,
+    module_name: &str,
+    package: &packages::Package,
+    path: &str,
+) -> bool {
+    previous_build
+        .state
+        .modules
+        .get(module_name)
+        .is_some_and(|cached| {
+            [Some(&cached.implementation), cached.interface.as_ref()]
+                .into_iter()
+                .flatten()
+                .any(|cached_file| cached_file.path == path && is_file_unchanged(package, path, cached_file))
+        })
+}
+
+/// Writes the state of all modules to the build folder of the root package after a build, so the
+/// next build can start from it. Returns the state that was written.
+pub fn write(build_state: &BuildState, build_dev_deps: bool) -> Option<PreviousBuild> {
+    let path = get_state_path(build_state)?;
+
+    let modules = build_state
+        .modules
+        .par_iter()
+        .filter_map(|(module_name, module)| match &module.source_type {
+            SourceType::SourceFile(source_file) => {
+                let package = build_state.get_package(&module.package_name)?;
+                let interface = match &source_file.interface {
+                    Some(interface) => Some(read_file(package, &interface.path, interface.last_modified)?),
+                    None => None,
+                };
+                let mut deps = module.deps.iter().cloned().collect::<Vec<String>>();
+                deps.sort();
+                Some((
+                    module_name.to_owned(),
+                    CachedModule {
+                        package_name: module.package_name.to_owned(),
+                        implementation: read_file(
+                            package,
+                            &source_file.implementation.path,
+                            source_file.implementation.last_modified,
+                        )?,
+                        interface,
+                        deps,
+                        dirty: is_module_dirty(module),
+                        dirty_reason: match &module.dirty_reason {
+                            Some(dirty_reason) if module.compile_dirty => Some(dirty_reason.to_owned()),
+                            _ if is_module_dirty(module) => Some(DirtyReason::ErrorsOrWarnings),
+                            _ => None,
+                        },
+                        cmi_hash: get_cmi_hash(package, &source_file.implementation.path),
+                        last_compiled_cmi: module.last_compiled_cmi,
+                        last_compiled_cmt: module.last_compiled_cmt,
+                        assets: get_assets(package, &source_file.implementation.path),
+                    },
+                ))
+            }
+            SourceType::MlMap(_) => None,
+        })
+        .collect::<BTreeMap<String, CachedModule>>();
+
+    let state = CachedState {
+        version: env!("CARGO_PKG_VERSION").to_string(),
+        rescript_version: build_state.rescript_version.to_owned(),
+        bsc_path: build_state.bsc_path.to_owned(),
+        build_dev_deps,
+        configs: get_config_hashes(build_state),
+        modules,
+    };
+
+    if let Ok(contents) = serde_json::to_string(&state) {
+        let _ = fs::write(path, contents);
+    }
+    Some(PreviousBuild { state })
+}
@@ -0,0 +1,342 @@
+use crate::build::build_types::BuildState;
+use crate::build::deps;
+use crate::config;
+use crate::helpers;
+use ahash::{AHashMap, AHashSet};
+use console::style;
+use std::collections::BTreeSet;
+
+// Checks the dependencies in the configs of the packages of the project against the modules
+// their sources actually use: dependencies that no module uses can be removed, and modules that
+// use a package that is not a dependency (but is only available through other dependencies)
+// should have it added.
+
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
+pub struct UnusedDependency {
+    pub package: String,
+    pub dependency: String,
+    pub dependency_type: &'static str,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
+pub struct UndeclaredDependency {
+    pub package: String,
+    pub module: String,
+    pub dep_module: String,
+    pub dep_package: String,
+    // the package is a dependency of one of the dependencies of the package
+    pub transitive: bool,
+}
+
+// the modules that are opened for all sources of a package with `-open` in the bsc-flags
+pub fn get_opened_modules(config: &config::Config) -> Vec<String> {
+    let bsc_flags = config::flatten_flags(&config.bsc_flags);
+    bsc_flags
+        .iter()
+        .zip(bsc_flags.iter().skip(1))
+        .filter(|(flag, _)| flag.as_str() == "-open")
+        .map(|(_, module)| module.split('.').next().unwrap_or_default().to_string())
+        .collect()
+}
+
+fn get_dependencies(config: &config::Config) -> Vec<(&String, &'static str)> {
+    let dependencies = config
+        .bs_dependencies
+        .iter()
+        .flatten()
+        .map(|dependency| (dependency, "bs-dependencies"));
+    let dev_dependencies = config
+        .bs_dev_dependencies
+        .iter()
+        .flatten()
+        .map(|dependency| (dependency, "bs-dev-dependencies"));
+    dependencies.chain(dev_dependencies).collect()
+}
+
+// the packages in the project, dependencies from node_modules are not checked
+fn get_checked_packages(build_state: &BuildState) -> Vec<&String> {
+    let mut packages = build_state
+        .packages
+        .values()
+        .filter(|package| package.is_root || package.is_local_dep)
+        .map(|package| &package.name)
+        .collect::<Vec<&String>>();
+    packages.sort();
+    packages
+}
+
+/// Finds the (dev) dependencies of packages that none of their modules use. A dependency is also
+/// used when the package opens one of its modules (or its namespace) with `-open`.
+pub fn find_unused_dependencies(build_state: &BuildState) -> Vec<UnusedDependency> {
+    let mut used_packages: AHashMap<&String, AHashSet<&String>> = AHashMap::new();
+    for module in build_state.modules.values() {
+        let used = used_packages.entry(&module.package_name).or_default();
+        used.extend(
+            module
+                .deps
+                .iter()
+                .filter_map(|dep| build_state.get_module(dep))
+                .map(|dep_module| &dep_module.package_name),
+        );
+    }
+
+    let mut unused_dependencies = get_checked_packages(build_state)
+        .into_iter()
+        .flat_map(|package_name| {
+            let package = &build_state.packages[package_name];
+            let opened_modules = get_opened_modules(&package.config);
+            let used = used_packages.get(package_name);
+            get_dependencies(&package.config)
+                .into_iter()
+                // the dev dependencies are only part of the build with --dev
+                .filter(|(dependency, _)| build_state.packages.contains_key(*dependency))
+                .filter(|(dependency, _)| !used.is_some_and(|used| used.contains(dependency)))
+                .filter(|(dependency, _)| {
+                    let dependency_package = &build_state.packages[*dependency];
+                    !opened_modules.iter().any(|opened_module| {
+                        dependency_package.namespace.to_suffix().as_ref() == Some(opened_module)
+                            || build_state
+                                .get_module(opened_module)
+                                .is_some_and(|module| &module.package_name == *dependency)
+                    })
+                })
+                .map(|(dependency, dependency_type)| UnusedDependency {
+                    package: package_name.to_owned(),
+                    dependency: dependency.to_owned(),
+                    dependency_type,
+                })
+                .collect::<Vec<UnusedDependency>>()
+        })
+        .collect::<Vec<UnusedDependency>>();
+    unused_dependencies.sort();
+    unused_dependencies
+}
+
+// all packages that can be reached through the dependencies of the package
+fn get_transitive_dependencies<'a>(build_state: &'a BuildState, package_name: &str) -> AHashSet<&'a String> {
+    let mut transitive_dependencies = AHashSet::new();
+    let mut queue = build_state
+        .get_package(package_name)
+        .map(|package| get_dependencies(&package.config))
+        .unwrap_or_default()
+        .into_iter()
+        .map(|(dependency, _)| dependency)
+        .collect::<Vec<&String>>();
+    while let Some(dependency) = queue.pop() {
+        if let Some(package) = build_state.get_package(dependency) {
+            for (dependency, _) in get_dependencies(&package.config) {
+                if transitive_dependencies.insert(dependency) {
+                    queue.push(dependency);
+                }
+            }
+        }
+    }
+    transitive_dependencies
+}
+
+/// Finds the modules that use a module of a package that is not a (dev) dependency of their
+/// package
+pub fn find_undeclared_dependencies(build_state: &BuildState) -> Vec<UndeclaredDependency> {
+    let checked_packages = get_checked_packages(build_state);
+    let mut undeclared_dependencies = build_state
+        .modules
+        .iter()
+        .filter(|(_, module)| checked_packages.contains(&&module.package_name))
+        .flat_map(|(module_name, module)| {
+            let transitive_dependencies = get_transitive_dependencies(build_state, &module.package_name);
+            deps::get_undeclared_dep_modules(build_state, module_name)
+                .into_iter()
+                .map(|dep_module| {
+                    let dep_package = build_state.modules[&dep_module].package_name.to_owned();
+                    UndeclaredDependency {
+                        package: module.package_name.to_owned(),
+                        module: module_name.to_owned(),
+                        transitive: transitive_dependencies.contains(&dep_package),
+                        dep_module,
+                        dep_package,
+                    }
+                })
+                .collect::<Vec<UndeclaredDependency>>()
+        })
+        .collect::<Vec<UndeclaredDependency>>();
+    undeclared_dependencies.sort();
+    undeclared_dependencies
+}
+
+pub fn format(
+    unused_dependencies: &[UnusedDependency],
+    undeclared_dependencies: &[UndeclaredDependency],
+) -> String {
+    let mut lines = vec![];
+    if !unused_dependencies.is_empty() {
+        lines.push("Unused dependencies:".to_string());
+        lines.extend(unused_dependencies.iter().map(|unused| {
+            format!(
+                "  {} is not used by {} (in {})",
+                unused.dependency, unused.package, unused.dependency_type
+            )
+        }));
+    }
+    if !undeclared_dependencies.is_empty() {
+        if !lines.is_empty() {
+            lines.push("".to_string());
+        }
+        lines.push("Undeclared dependencies:".to_string());
+        lines.extend(undeclared_dependencies.iter().map(|undeclared| {
+            format!(
+                "  {} (in {}) uses {} of {}, which is {}",
+                helpers::format_namespaced_module_name(&undeclared.module),
+                undeclared.package,
+                helpers::format_namespaced_module_name(&undeclared.dep_module),
+                undeclared.dep_package,
+                if undeclared.transitive {
+                    "only available through other dependencies"
+                } else {
+                    "not a dependency"
+                }
+            )
+        }));
+    }
+    lines.join("\n")
+}
+
+/// Prints the unused and undeclared dependencies, returns false when there are any
+pub fn check(build_state: &BuildState) -> bool {
+    let unused_dependencies = find_unused_dependencies(build_state);
+    let undeclared_dependencies = find_undeclared_dependencies(build_state);
+    if unused_dependencies.is_empty() && undeclared_dependencies.is_empty() {
+        println!("No unused or undeclared dependencies found");
+        return true;
+    }
+
+    println!("{}", format(&unused_dependencies, &undeclared_dependencies));
+    let packages = unused_dependencies
+        .iter()
+        .map(|unused| &unused.package)
+        .chain(
+            undeclared_dependencies
+                .iter()
+                .map(|undeclared| &undeclared.package),
+        )
+        .collect::<BTreeSet<&String>>();
+    println!(
+        "\nUpdate the {} and {} in the config of {}",
+        style("bs-dependencies").bold(),
+        style("bs-dev-dependencies").bold(),
+        packages.into_iter().cloned().collect::<Vec<String>>().join(", ")
+    );
+    false
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::build::build_types::{MlMap, Module, SourceType};
+    use crate::build::packages::{Namespace, Package};
+
+    fn create_package(config: &str, is_root: bool) -> Package {
+        let config = serde_json::from_str::<config::Config>(config).unwrap();
+        Package {
+            name: config.name.to_owned(),
+            config,
+            source_folders: AHashSet::new(),
+            source_files: None,
+            namespace: Namespace::NoNamespace,
+            modules: None,
+            path: "".to_string(),
+            dirs: None,
+            private_modules: AHashSet::new(),
+            is_pinned_dep: false,
+            is_local_dep: true,
+            is_root,
+        }
+    }
+
+    fn create_module(package_name: &str, deps: &[&str], is_type_dev: bool) -> Module {
+        Module {
+            source_type: SourceType::MlMap(MlMap { parse_dirty: false }),
+            deps: deps.iter().map(|dep| dep.to_string()).collect(),
+            dependents: AHashSet::new(),
+            package_name: package_name.to_string(),
+            is_type_dev,
+            compile_dirty: false,
+            dirty_reason: None,
+            last_compiled_cmi: None,
+            last_compiled_cmt: None,
+            deps_dirty: false,
+        }
+    }
+
+    #[test]
+    fn test_find_unused_dev_dependencies() {
+        let packages = [
+            create_package(
+                r#"{ "name": "main", "bs-dev-dependencies": ["tester", "unused"] }"#,
+                true,
+            ),
+            create_package(r#"{ "name": "tester" }"#, false),
+            create_package(r#"{ "name": "unused" }"#, false),
+        ];
+        let mut build_state = BuildState::new(
+            "".to_string(),
+            "main".to_string(),
+            packages
+                .into_iter()
+                .map(|package| (package.name.to_owned(), package))
+                .collect(),
+            None,
+            "".to_string(),
+            "".to_string(),
+        );
+        build_state.insert_module("AppTest", create_module("main", &["Expect"], true));
+        build_state.insert_module("Expect", create_module("tester", &[], false));
+        build_state.insert_module("Unused", create_module("unused", &[], false));
+
+        assert_eq!(
+            find_unused_dependencies(&build_state),
+            vec![UnusedDependency {
+                package: "main".to_string(),
+                dependency: "unused".to_string(),
+                dependency_type: "bs-dev-dependencies",
+            }]
+        );
+    }
+
+    #[test]
+    fn test_get_opened_modules() {
+        let config = serde_json::from_str::<config::Config>(
+            r#"{ "name": "main", "bsc-flags": ["-open RescriptCore", "-bs-super-errors", "-open Belt.Array"] }"#,
+        )
+        .unwrap();
+        assert_eq!(get_opened_modules(&config), vec!["RescriptCore", "Belt"]);
+    }
+
+    #[test]
+    fn test_format() {
+        let formatted = format(
+            &[UnusedDependency {
+                package: "main".to_string(),
+                dependency: "@rescript/react".to_string(),
+                dependency_type: "bs-dependencies",
+            }],
+            &[UndeclaredDependency {
+                package: "main".to_string(),
+                module: "App".to_string(),
+                dep_module: "Button-Ui".to_string(),
+                dep_package: "ui".to_string(),
+                transitive: true,
+            }],
+        );
+        assert_eq!(
+            formatted,
+            [
+                "Unused dependencies:",
+                "  @rescript/react is not used by main (in bs-dependencies)",
+                "",
+                "Undeclared dependencies:",
+                "  App (in main) uses Ui.Button of ui, which is only available through other dependencies",
+            ]
+            .join("\n")
+        );
+    }
+}
@@ -4,7 +4,7 @@ use anyhow::Result;
 use convert_case::{Case, Casing};
 use serde::Deserialize;
 use std::fs;
-use std::path::{Path, PathBuf};
+use std::path::{Component, Path, PathBuf};
 
 #[derive(Deserialize, Debug, Clone)]
 #[serde(untagged)]
@@ -27,10 +27,88 @@ pub struct PackageSource {
     pub subdirs: Option<Subdirs>,
     #[serde(rename = "type")]
     pub type_: Option<String>,
+    pub generators: Option<Vec<BuildGenerator>>,
+    pub files: Option<SourceFiles>,
+    pub public: Option<Public>,
 }
 
 impl Eq for PackageSource {}
 
+/// The modules of a source folder that can be used by other packages. This is either `"all"`, or
+/// a list of module names. When it's not set, all modules are public.
+#[derive(Deserialize, Debug, Clone, PartialEq, Eq, Hash)]
+#[serde(untagged)]
+pub enum Public {
+    Modules(Vec<String>),
+    All(AllModules),
+}
+
+#[derive(Deserialize, Debug, Clone, PartialEq, Eq, Hash)]
+pub enum AllModules {
+    #[serde(rename = "all")]
+    All,
+}
+
+impl Public {
+    pub fn is_public(&self, module_name: &str) -> bool {
+        match self {
+            Public::Modules(modules) => modules.iter().any(|module| module == module_name),
+            Public::All(_) => true,
+        }
+    }
+}
+
+/// The `files` of a source folder, either an explicit list of files to include, or a File-Object
+/// that excludes files by name, and / or only includes the files matching a regex (`slow-re`).
+#[derive(Deserialize, Debug, Clone, PartialEq, Eq, Hash)]
+#[serde(untagged)]
+pub enum SourceFiles {
+    Explicit(Vec<String>),
+    Filtered(SourceFilesFilter),
+}
+
+#[derive(Deserialize, Debug, Clone, PartialEq, Eq, Hash)]
+pub struct SourceFilesFilter {
+    #[serde(rename = "slow-re")]
+    pub slow_re: Option<String>,
+    pub excludes: Option<Vec<String>>,
+}
+
+/// A `Rule-Generator` from the package config, the command can use `$in` and `$out`, which are
+/// replaced by the inputs and outputs of the edge that uses the rule.
+#[derive(Deserialize, Debug, Clone, PartialEq, Eq, Hash)]
+pub struct RuleGenerator {
+    pub name: String,
+    pub command: String,
+}
+
+/// A `Build-Generator` from a source folder. The edge is written as
+/// `["Output.res", ":", "input.graphql"]`, with the paths relative to the source folder.
+#[derive(Deserialize, Debug, Clone, PartialEq, Eq, Hash)]
+pub struct BuildGenerator {
+    pub name: String,
+    pub edge: Vec<String>,
+}
+
+impl BuildGenerator {
+    pub fn get_outputs(&self) -> Vec<String> {
+        self.edge
+            .iter()
+            .take_while(|file| *file != ":")
+            .cloned()
+            .collect()
+    }
+
+    pub fn get_inputs(&self) -> Vec<String> {
+        self.edge
+            .iter()
+            .skip_while(|file| *file != ":")
+            .skip(1)
+            .cloned()
+            .collect()
+    }
+}
+
 #[derive(Deserialize, Debug, Clone, PartialEq, Hash)]
 #[serde(untagged)]
 pub enum Source {
@@ -52,6 +130,9 @@ impl Source {
                 dir: dir.to_string(),
                 subdirs: None,
                 type_: Some(type_),
+                generators: None,
+                files: None,
+                public: None,
             }),
             (Source::Qualified(package_source), type_) => Source::Qualified(PackageSource {
                 type_,
@@ -74,11 +155,17 @@ impl Source {
                     .to_string(),
                 subdirs: None,
                 type_: self.get_type(),
+                generators: None,
+                files: None,
+                public: None,
             },
             Source::Qualified(PackageSource {
                 dir,
                 type_,
                 subdirs: Some(Subdirs::Recurse(should_recurse)),
+                generators,
+                files,
+                public,
             }) => PackageSource {
                 dir: sub_path
                     .map(|p| p.join(Path::new(dir)))
@@ -87,8 +174,18 @@ impl Source {
                     .to_string(),
                 subdirs: Some(Subdirs::Recurse(*should_recurse)),
                 type_: type_.to_owned(),
+                generators: generators.to_owned(),
+                files: files.to_owned(),
+                public: public.to_owned(),
             },
-            Source::Qualified(PackageSource { dir, type_, .. }) => PackageSource {
+            Source::Qualified(PackageSource {
+                dir,
+                type_,
+                generators,
+                files,
+                public,
+                ..
+            }) => PackageSource {
                 dir: sub_path
                     .map(|p| p.join(Path::new(dir)))
                     .unwrap_or(Path::new(dir).to_path_buf())
@@ -96,6 +193,9 @@ impl Source {
                     .to_string(),
                 subdirs: None,
                 type_: type_.to_owned(),
+                generators: generators.to_owned(),
+                files: files.to_owned(),
+                public: public.to_owned(),
             },
         }
     }
@@ -132,6 +232,18 @@ impl PackageSpec {
     }
 }
 
+#[derive(Deserialize, Debug, Clone)]
+pub struct Entry {
+    pub backend: Option<String>,
+    #[serde(rename = "main-module")]
+    pub main_module: String,
+}
+
+#[derive(Deserialize, Debug, Clone)]
+pub struct JsPostBuild {
+    pub cmd: String,
+}
+
 #[derive(Deserialize, Debug, Clone)]
 #[serde(untagged)]
 pub enum Error {
@@ -197,6 +309,9 @@ pub struct Config {
     // In the case of monorepos, the root source won't necessarily have to have sources. It can
     // just be sources in packages
     pub sources: Option<OneOrMore<Source>>,
+    #[serde(rename = "ignored-dirs")]
+    pub ignored_dirs: Option<Vec<String>>,
+    pub generators: Option<Vec<RuleGenerator>>,
     #[serde(rename = "package-specs")]
     pub package_specs: Option<OneOrMore<PackageSpec>>,
     pub warnings: Option<Warnings>,
@@ -217,6 +332,9 @@ pub struct Config {
     pub uncurried: Option<bool>,
     #[serde(rename = "gentypeconfig")]
     pub gentype_config: Option<GenTypeConfig>,
+    #[serde(rename = "js-post-build")]
+    pub js_post_build: Option<JsPostBuild>,
+    pub entries: Option<Vec<Entry>>,
     // this is a new feature of rewatch, and it's not part of the bsconfig.json spec
     #[serde(rename = "namespace-entry")]
     pub namespace_entry: Option<String>,
@@ -438,6 +556,16 @@ impl Config {
         }
     }
 
+    /// The main modules of the entries for the js backend, we don't build the other backends
+    pub fn get_entry_modules(&self) -> Vec<String> {
+        self.entries
+            .iter()
+            .flatten()
+            .filter(|entry| entry.backend.as_deref().unwrap_or("js") == "js")
+            .map(|entry| entry.main_module.to_owned())
+            .collect()
+    }
+
     pub fn get_gentype_arg(&self) -> Vec<String> {
         match &self.gentype_config {
             Some(_) => vec!["-bs-gentype".to_string()],
@@ -462,6 +590,45 @@ impl Config {
             .or(self.suffix.clone())
             .unwrap_or(".js".to_string())
     }
+
+    pub fn get_generator_command(&self, name: &str) -> Option<String> {
+        self.generators
+            .as_ref()
+            .and_then(|generators| generators.iter().find(|generator| generator.name == name))
+            .map(|generator| generator.command.to_owned())
+    }
+
+    /// Checks whether a directory (relative to the package root) is listed in `ignored-dirs`. An
+    /// entry with a single component (`__generated__`) matches a directory with that name at any
+    /// depth, an entry with multiple components (`src/fixtures`) only matches that exact path.
+    pub fn is_ignored_dir(&self, dir: &Path) -> bool {
+        let dir_components = dir
+            .components()
+            .filter(|c| !matches!(c, Component::CurDir))
+            .collect::<Vec<Component>>();
+        if dir_components.is_empty() {
+            return false;
+        }
+
+        self.ignored_dirs.as_ref().is_some_and(|ignored_dirs| {
+            ignored_dirs.iter().any(|ignored_dir| {
+                let ignored_components = Path::new(ignored_dir)
+                    .components()
+                    .filter(|c| !matches!(c, Component::CurDir))
+                    .collect::<Vec<Component>>();
+                match ignored_components.as_slice() {
+                    [] => false,
+                    [name] => dir_components.last() == Some(name),
+                    _ => ignored_components == dir_components,
+                }
+            })
+        })
+    }
+
+    /// Checks whether a path (relative to the package root) is inside one of the `ignored-dirs`
+    pub fn is_ignored_path(&self, path: &Path) -> bool {
+        path.ancestors().any(|dir| self.is_ignored_dir(dir))
+    }
 }
 
 #[cfg(test)]
@@ -735,6 +902,176 @@ mod tests {
         );
     }
 
+    #[test]
+    fn test_ignored_dirs() {
+        let json = r#"
+        {
+            "name": "testrepo",
+            "sources": {
+                "dir": "src",
+                "subdirs": true
+            },
+            "ignored-dirs": [ "__generated__", "src/fixtures/" ]
+        }
+        "#;
+
+        let config = serde_json::from_str::<Config>(json).unwrap();
+        assert!(config.is_ignored_dir(Path::new("src/__generated__")));
+        assert!(config.is_ignored_dir(Path::new("src/nested/__generated__")));
+        assert!(config.is_ignored_dir(Path::new("./src/fixtures")));
+        assert!(!config.is_ignored_dir(Path::new("src/other/fixtures")));
+        assert!(!config.is_ignored_dir(Path::new("src")));
+        assert!(config.is_ignored_path(Path::new("src/fixtures/deep/Fixture.res")));
+        assert!(!config.is_ignored_path(Path::new("src/App.res")));
+    }
+
+    #[test]
+    fn test_generators() {
+        let json = r#"
+        {
+            "name": "testrepo",
+            "generators": [
+                { "name": "cpp", "command": "sed 's/OCAML/3/' $in > $out" }
+            ],
+            "sources": {
+                "dir": "src",
+                "generators": [
+                    { "name": "cpp", "edge": ["Test.res", "Test.resi", ":", "test.cpp.res"] }
+                ]
+            }
+        }
+        "#;
+
+        let config = serde_json::from_str::<Config>(json).unwrap();
+        assert_eq!(
+            config.get_generator_command("cpp"),
+            Some("sed 's/OCAML/3/' $in > $out".to_string())
+        );
+        assert_eq!(config.get_generator_command("other"), None);
+        if let Some(OneOrMore::Single(source)) = config.sources {
+            let source = source.to_qualified_without_children(None);
+            let generators = source.generators.unwrap();
+            assert_eq!(generators[0].get_outputs(), vec!["Test.res", "Test.resi"]);
+            assert_eq!(generators[0].get_inputs(), vec!["test.cpp.res"]);
+        } else {
+            dbg!(config.sources);
+            unreachable!()
+        }
+    }
+
+    #[test]
+    fn test_js_post_build() {
+        let json = r#"
+        {
+            "name": "testrepo",
+            "sources": {
+                "dir": "src",
+                "subdirs": true
+            },
+            "js-post-build": {
+                "cmd": "node ../../postProcess.js"
+            }
+        }
+        "#;
+
+        let config = serde_json::from_str::<Config>(json).unwrap();
+        assert_eq!(
+            config.js_post_build.map(|js_post_build| js_post_build.cmd),
+            Some("node ../../postProcess.js".to_string())
+        );
+    }
+
+    #[test]
+    fn test_source_files() {
+        let json = r#"
+        {
+            "name": "testrepo",
+            "sources": [
+                { "dir": "src", "files": ["App.res", "App.resi"] },
+                { "dir": "scratch", "files": { "slow-re": ".*\\.res$", "excludes": ["Scratch.res"] } }
+            ]
+        }
+        "#;
+
+        let config = serde_json::from_str::<Config>(json).unwrap();
+        if let Some(OneOrMore::Multiple(sources)) = config.sources {
+            assert_eq!(
+                sources[0].to_qualified_without_children(None).files,
+                Some(SourceFiles::Explicit(vec![
+                    "App.res".to_string(),
+                    "App.resi".to_string()
+                ]))
+            );
+            assert_eq!(
+                sources[1].to_qualified_without_children(None).files,
+                Some(SourceFiles::Filtered(SourceFilesFilter {
+                    slow_re: Some(".*\\.res$".to_string()),
+                    excludes: Some(vec!["Scratch.res".to_string()]),
+                }))
+            );
+        } else {
+            dbg!(config.sources);
+            unreachable!()
+        }
+    }
+
+    #[test]
+    fn test_public() {
+        let json = r#"
+        {
+            "name": "testrepo",
+            "sources": [
+                { "dir": "src", "public": ["App"] },
+                { "dir": "lib", "public": "all" },
+                { "dir": "other" }
+            ]
+        }
+        "#;
+
+        let config = serde_json::from_str::<Config>(json).unwrap();
+        if let Some(OneOrMore::Multiple(sources)) = config.sources {
+            let public = sources[0].to_qualified_without_children(None).public.unwrap();
+            assert!(public.is_public("App"));
+            assert!(!public.is_public("Internal"));
+            let public = sources[1].to_qualified_without_children(None).public.unwrap();
+            assert!(public.is_public("Internal"));
+            assert_eq!(sources[2].to_qualified_without_children(None).public, None);
+        } else {
+            dbg!(config.sources);
+            unreachable!()
+        }
+    }
+
+    #[test]
+    fn test_public_other_string() {
+        let json = r#"
+        {
+            "name": "testrepo",
+            "sources": { "dir": "src", "public": "none" }
+        }
+        "#;
+
+        assert!(serde_json::from_str::<Config>(json).is_err());
+    }
+
+    #[test]
+    fn test_entries() {
+        let json = r#"
+        {
+            "name": "my-monorepo",
+            "sources": [ { "dir": "src/", "subdirs": true } ],
+            "entries": [
+                { "backend": "js", "main-module": "App" },
+                { "backend": "native", "main-module": "Server" },
+                { "main-module": "Admin" }
+            ]
+        }
+        "#;
+
+        let config = serde_json::from_str::<Config>(json).unwrap();
+        assert_eq!(config.get_entry_modules(), vec!["App", "Admin"]);
+    }
+
     #[test]
     fn test_check_if_rescript11_or_higher() {
         assert_eq!(check_if_rescript11_or_higher("11.0.0"), Ok(true));
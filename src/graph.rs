@@ -0,0 +1,466 @@
+use crate::build::build_types::{BuildState, Module};
+use crate::build::compile::dependency_cycle;
+use crate::build::deps;
+use crate::helpers;
+use ahash::AHashMap;
+use serde_json::json;
+use std::collections::{BTreeMap, BTreeSet, VecDeque};
+
+// Exports the dependency graph of the modules (or of the packages) of a build, to visualise it or
+// to process it with other tools.
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, clap::ValueEnum)]
+pub enum Format {
+    /// Graphviz DOT
+    Dot,
+    /// A JSON object with the nodes and the edges
+    Json,
+    /// A Mermaid flowchart
+    Mermaid,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
+pub struct Edge {
+    pub from: String,
+    pub to: String,
+    // a dev dependency of a package
+    pub dev: bool,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct Graph {
+    // the nodes with the package they belong to (for a module graph)
+    pub nodes: BTreeMap<String, Option<String>>,
+    pub edges: BTreeSet<Edge>,
+}
+
+impl Graph {
+    /// The modules and their dependencies, edges point from a module to its dependency
+    pub fn from_modules(build_state: &BuildState) -> Self {
+        let nodes = build_state
+            .modules
+            .iter()
+            .map(|(module_name, module)| (module_name.to_owned(), Some(module.package_name.to_owned())))
+            .collect();
+        let edges = build_state
+            .modules
+            .iter()
+            .flat_map(|(module_name, module)| {
+                module.deps.iter().map(|dep| Edge {
+                    from: module_name.to_owned(),
+                    to: dep.to_owned(),
+                    dev: false,
+                })
+            })
+            .collect();
+        Self { nodes, edges }
+    }
+
+    /// The packages and their (dev) dependencies of the rescript.json, as far as they are part of
+    /// the build
+    pub fn from_packages(build_state: &BuildState) -> Self {
+        let nodes = build_state
+            .packages
+            .keys()
+            .map(|package_name| (package_name.to_owned(), None))
+            .collect();
+        let edges = build_state
+            .packages
+            .values()
+            .flat_map(|package| {
+                let dependencies = package
+                    .config
+                    .bs_dependencies
+                    .to_owned()
+                    .unwrap_or_default()
+                    .into_iter()
+                    .map(|dependency| (dependency, false));
+                let dev_dependencies = package
+                    .config
+                    .bs_dev_dependencies
+                    .to_owned()
+                    .unwrap_or_default()
+                    .into_iter()
+                    .map(|dependency| (dependency, true));
+                dependencies
+                    .chain(dev_dependencies)
+                    .filter(|(dependency, _)| build_state.packages.contains_key(dependency))
+                    .map(|(dependency, dev)| Edge {
+                        from: package.name.to_owned(),
+                        to: dependency,
+                        dev,
+                    })
+                    .collect::<Vec<Edge>>()
+            })
+            .collect();
+        Self { nodes, edges }
+    }
+
+    /// Finds a node by its name, a namespaced module can also be given as `Namespace.Module`
+    pub fn find_node(&self, name: &str) -> Result<String, String> {
+        if self.nodes.contains_key(name) {
+            return Ok(name.to_string());
+        }
+        self.nodes
+            .keys()
+            .find(|node| helpers::format_namespaced_module_name(node) == name)
+            .cloned()
+            .ok_or(format!("Could not find {} in the dependency graph", name))
+    }
+
+    /// All nodes that can be reached from the node (including the node itself), following the
+    /// edges, or following them in reverse
+    pub fn get_reachable(&self, node: &str, reverse: bool) -> BTreeSet<String> {
+        let mut adjacent: AHashMap<&str, Vec<&str>> = AHashMap::new();
+        for edge in self.edges.iter() {
+            let (from, to) = if reverse {
+                (&edge.to, &edge.from)
+            } else {
+                (&edge.from, &edge.to)
+            };
+            adjacent.entry(from).or_default().push(to);
+        }
+
+        let mut reachable = BTreeSet::from([node.to_string()]);
+        let mut queue = VecDeque::from([node]);
+        while let Some(current) = queue.pop_front() {
+            for to in adjacent.get(current).into_iter().flatten() {
+                if reachable.insert(to.to_string()) {
+                    queue.push_back(to);
+                }
+            }
+        }
+        reachable
+    }
+
+    /// Keeps the nodes that match the filter, and the edges between them
+    pub fn retain(&mut self, filter: impl Fn(&String, &Option<String>) -> bool) {
+        self.nodes.retain(|node, package| filter(node, package));
+        let nodes = &self.nodes;
+        self.edges
+            .retain(|edge| nodes.contains_key(&edge.from) && nodes.contains_key(&edge.to));
+    }
+
+    fn get_groups(&self) -> BTreeMap<Option<&String>, Vec<&String>> {
+        let mut groups: BTreeMap<Option<&String>, Vec<&String>> = BTreeMap::new();
+        for (node, package) in self.nodes.iter() {
+            groups.entry(package.as_ref()).or_default().push(node);
+        }
+        groups
+    }
+
+    pub fn to_dot(&self) -> String {
+        let mut lines = vec!["digraph dependencies {".to_string()];
+        for (package, nodes) in self.get_groups() {
+            let nodes = nodes.iter().map(|node| format!("{:?};", node));
+            match package {
+                Some(package) => {
+                    lines.push(format!("  subgraph {:?} {{", format!("cluster_{}", package)));
+                    lines.push(format!("    label = {:?};", package));
+                    lines.extend(nodes.map(|node| format!("    {}", node)));
+                    lines.push("  }".to_string());
+                }
+                None => lines.extend(nodes.map(|node| format!("  {}", node))),
+            }
+        }
+        for edge in self.edges.iter() {
+            let style = if edge.dev { " [style = dashed]" } else { "" };
+            lines.push(format!("  {:?} -> {:?}{};", edge.from, edge.to, style));
+        }
+        lines.push("}".to_string());
+        lines.join("\n")
+    }
+
+    pub fn to_mermaid(&self) -> String {
+        // module names can contain characters that are not allowed in the ids of mermaid
+        let ids = self
+            .nodes
+            .keys()
+            .enumerate()
+            .map(|(index, node)| (node, format!("n{}", index)))
+            .collect::<BTreeMap<&String, String>>();
+        let mut lines = vec!["flowchart LR".to_string()];
+        for (index, (package, nodes)) in self.get_groups().into_iter().enumerate() {
+            let nodes = nodes
+                .iter()
+                .map(|node| format!("{}[\"{}\"]", ids[node], node.replace('"', "#quot;")));
+            match package {
+                Some(package) => {
+                    lines.push(format!("  subgraph g{}[\"{}\"]", index, package));
+                    lines.extend(nodes.map(|node| format!("    {}", node)));
+                    lines.push("  end".to_string());
+                }
+                None => lines.extend(nodes.map(|node| format!("  {}", node))),
+            }
+        }
+        for edge in self.edges.iter() {
+            let arrow = if edge.dev { "-.->" } else { "-->" };
+            lines.push(format!("  {} {} {}", ids[&edge.from], arrow, ids[&edge.to]));
+        }
+        lines.join("\n")
+    }
+
+    pub fn to_json(&self) -> serde_json::Value {
+        json!({
+            "nodes": self
+                .nodes
+                .iter()
+                .map(|(node, package)| match package {
+                    Some(package) => json!({ "id": node, "package": package }),
+                    None => json!({ "id": node }),
+                })
+                .collect::<Vec<serde_json::Value>>(),
+            "edges": self
+                .edges
+                .iter()
+                .map(|edge| json!({ "from": edge.from, "to": edge.to, "dev": edge.dev }))
+                .collect::<Vec<serde_json::Value>>(),
+        })
+    }
+
+    pub fn format(&self, format: Format) -> String {
+        match format {
+            Format::Dot => self.to_dot(),
+            Format::Json => self.to_json().to_string(),
+            Format::Mermaid => self.to_mermaid(),
+        }
+    }
+}
+
+/// Prints the module graph (or the package graph) of the build. It can be restricted to some
+/// packages, and to the dependencies and/or the dependents of a node.
+pub fn print(
+    build_state: &BuildState,
+    format: Format,
+    package_graph: bool,
+    packages: &[String],
+    deps_of: &Option<String>,
+    dependents_of: &Option<String>,
+) -> Result<(), String> {
+    let mut graph = if package_graph {
+        Graph::from_packages(build_state)
+    } else {
+        Graph::from_modules(build_state)
+    };
+
+    for (node, reverse) in [(deps_of, false), (dependents_of, true)] {
+        if let Some(node) = node {
+            let reachable = graph.get_reachable(&graph.find_node(node)?, reverse);
+            graph.retain(|node, _| reachable.contains(node));
+        }
+    }
+    if !packages.is_empty() {
+        graph.retain(|node, package| packages.contains(package.as_ref().unwrap_or(node)));
+    }
+
+    println!("{}", graph.format(format));
+    Ok(())
+}
+
+// a module in a chain of dependencies, with the AST files the dependency on it was read from
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Step {
+    pub module: String,
+    pub package: String,
+    pub sources: Vec<&'static str>,
+}
+
+fn format_sources(sources: &[&str]) -> String {
+    match sources {
+        [] => "the namespace".to_string(),
+        sources => sources.join(" and "),
+    }
+}
+
+pub fn format_path(path: &[Step]) -> String {
+    let mut lines = vec![];
+    for (index, step) in path.iter().enumerate() {
+        let module = format!(
+            "{} ({})",
+            helpers::format_namespaced_module_name(&step.module),
+            step.package
+        );
+        match index.checked_sub(1).map(|previous| &path[previous]) {
+            None => lines.push(module),
+            Some(previous) => {
+                let mut line = format!(" → {} via {}", module, format_sources(&step.sources));
+                if previous.package != step.package {
+                    line.push_str(&format!(
+                        ", crossing from package {} to {}",
+                        previous.package, step.package
+                    ));
+                }
+                lines.push(line);
+            }
+        }
+    }
+    lines.join("\n")
+}
+
+/// Explains why a module depends on another one, with the shortest chain of dependencies between
+/// them. Every dependency tells if it's used in the implementation (.ast) or the interface (.iast)
+/// of the module before it.
+pub fn why(build_state: &BuildState, from: &str, to: &str) -> Result<String, String> {
+    let graph = Graph::from_modules(build_state);
+    let from = graph.find_node(from)?;
+    let to = graph.find_node(to)?;
+    let modules = build_state.modules.iter().collect::<Vec<(&String, &Module)>>();
+
+    let path = match dependency_cycle::find_path(&modules, &from, &to) {
+        Some(path) => path,
+        None => {
+            return Ok(format!(
+                "{} doesn't depend on {}",
+                helpers::format_namespaced_module_name(&from),
+                helpers::format_namespaced_module_name(&to)
+            ))
+        }
+    };
+
+    let steps = path
+        .iter()
+        .enumerate()
+        .map(|(index, module_name)| {
+            let sources = match index.checked_sub(1) {
+                Some(previous) => {
+                    let (implementation_deps, interface_deps) =
+                        deps::get_implementation_and_interface_deps(build_state, &path[previous])
+                            .unwrap_or_default();
+                    [(implementation_deps, ".ast"), (interface_deps, ".iast")]
+                        .into_iter()
+                        .filter(|(deps, _)| deps.contains(module_name))
+                        .map(|(_, source)| source)
+                        .collect()
+                }
+                None => vec![],
+            };
+            Step {
+                module: module_name.to_owned(),
+                package: build_state.modules[module_name].package_name.to_owned(),
+                sources,
+            }
+        })
+        .collect::<Vec<Step>>();
+
+    Ok(format_path(&steps))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn create_graph() -> Graph {
+        let edge = |from: &str, to: &str| Edge {
+            from: from.to_string(),
+            to: to.to_string(),
+            dev: false,
+        };
+        Graph {
+            nodes: BTreeMap::from([
+                ("App".to_string(), Some("main".to_string())),
+                ("Utils".to_string(), Some("main".to_string())),
+                ("Button-Ui".to_string(), Some("ui".to_string())),
+            ]),
+            edges: BTreeSet::from([edge("App", "Button-Ui"), edge("Button-Ui", "Utils")]),
+        }
+    }
+
+    #[test]
+    fn test_find_node() {
+        let graph = create_graph();
+        assert_eq!(graph.find_node("App"), Ok("App".to_string()));
+        assert_eq!(graph.find_node("Ui.Button"), Ok("Button-Ui".to_string()));
+        assert!(graph.find_node("Missing").is_err());
+    }
+
+    #[test]
+    fn test_reachable() {
+        let graph = create_graph();
+        assert_eq!(
+            graph.get_reachable("Button-Ui", false),
+            BTreeSet::from(["Button-Ui".to_string(), "Utils".to_string()])
+        );
+        assert_eq!(
+            graph.get_reachable("Button-Ui", true),
+            BTreeSet::from(["Button-Ui".to_string(), "App".to_string()])
+        );
+    }
+
+    #[test]
+    fn test_retain() {
+        let mut graph = create_graph();
+        graph.retain(|_, package| package.as_deref() == Some("main"));
+        assert_eq!(graph.nodes.len(), 2);
+        assert!(graph.edges.is_empty());
+    }
+
+    #[test]
+    fn test_format_path() {
+        let step = |module: &str, package: &str, sources: Vec<&'static str>| Step {
+            module: module.to_string(),
+            package: package.to_string(),
+            sources,
+        };
+        assert_eq!(
+            format_path(&[
+                step("App", "main", vec![]),
+                step("Button-Ui", "ui", vec![".ast", ".iast"]),
+                step("Icon-Ui", "ui", vec![".iast"]),
+            ]),
+            [
+                "App (main)",
+                " → Ui.Button (ui) via .ast and .iast, crossing from package main to ui",
+                " → Ui.Icon (ui) via .iast",
+            ]
+            .join("\n")
+        );
+    }
+
+    #[test]
+    fn test_formats() {
+        let graph = create_graph();
+        assert_eq!(
+            graph.to_dot(),
+            [
+                "digraph dependencies {",
+                "  subgraph \"cluster_main\" {",
+                "    label = \"main\";",
+                "    \"App\";",
+                "    \"Utils\";",
+                "  }",
+                "  subgraph \"cluster_ui\" {",
+                "    label = \"ui\";",
+                "    \"Button-Ui\";",
+                "  }",
+                "  \"App\" -> \"Button-Ui\";",
+                "  \"Button-Ui\" -> \"Utils\";",
+                "}",
+            ]
+            .join("\n")
+        );
+        assert_eq!(
+            graph.to_mermaid(),
+            [
+                "flowchart LR",
+                "  subgraph g0[\"main\"]",
+                "    n0[\"App\"]",
+                "    n2[\"Utils\"]",
+                "  end",
+                "  subgraph g1[\"ui\"]",
+                "    n1[\"Button-Ui\"]",
+                "  end",
+                "  n0 --> n1",
+                "  n1 --> n2",
+            ]
+            .join("\n")
+        );
+        assert_eq!(
+            graph.to_json()["nodes"][1],
+            json!({ "id": "Button-Ui", "package": "ui" })
+        );
+        assert_eq!(
+            graph.to_json()["edges"][0],
+            json!({ "from": "App", "to": "Button-Ui", "dev": false })
+        );
+    }
+}
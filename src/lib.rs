@@ -1,8 +1,12 @@
+pub mod affected;
 pub mod build;
+pub mod check_deps;
 pub mod cmd;
 pub mod config;
+pub mod graph;
 pub mod helpers;
 pub mod lock;
 pub mod queue;
+pub mod sarif;
 pub mod sourcedirs;
 pub mod watcher;
@@ -1,11 +1,12 @@
 use anyhow::Result;
-use clap::{Parser, ValueEnum};
+use clap::{error::ErrorKind, CommandFactory, Parser, ValueEnum};
 use clap_verbosity_flag::InfoLevel;
 use log::LevelFilter;
 use regex::Regex;
 use std::io::Write;
 
-use rewatch::{build, cmd, lock, watcher};
+use rewatch::build::diagnostics::Reporter;
+use rewatch::{affected, build, check_deps, cmd, graph, lock, watcher};
 
 #[derive(Debug, Clone, ValueEnum)]
 enum Command {
@@ -15,6 +16,18 @@ enum Command {
     Watch,
     /// Clean the build artifacts
     Clean,
+    /// Print the dependency graph of the modules (or the packages)
+    Graph,
+    /// Explain why a module depends on another module: `rewatch why <MODULE_A> <MODULE_B>`
+    Why,
+    /// Report dependencies in the configs that are not used, and modules that use packages that
+    /// are not a dependency. The dev source folders and bs-dev-dependencies are always checked
+    CheckDeps,
+    /// Print the modules and packages that are affected by changed files as JSON. The files are
+    /// read from stdin (one per line, like `git diff --name-only`, so relative to the top level of
+    /// the git repository, or to the current directory outside of git), or are the files that
+    /// changed since --since
+    Affected,
 }
 
 /// Rewatch is an alternative build system for the Rescript Compiler bsb (which uses Ninja internally). It strives
@@ -29,6 +42,10 @@ struct Args {
     /// The relative path to where the main rescript.json resides. IE - the root of your project.
     folder: Option<String>,
 
+    /// The two modules for why, the other commands don't take modules. The folder can be left out:
+    /// `rewatch why ModuleA ModuleB`
+    modules: Vec<String>,
+
     /// Filter allows for a regex to be supplied which will filter the files to be compiled. For
     /// instance, to filter out test files for compilation while doing feature work.
     #[arg(short, long)]
@@ -65,10 +82,11 @@ struct Args {
     #[arg(long)]
     compiler_args: Option<String>,
 
-    /// This is the flag to also compile development dependencies
+    /// This is the flag to also compile the bs-dev-dependencies of the root and local packages,
+    /// and the source folders with "type": "dev". Without it, those folders are skipped, and not listed in .sourcedirs.json.
     /// It's important to know that we currently do not discern between project src, and
-    /// dependencies. So enabling this flag will enable building _all_ development dependencies of
-    /// _all_ packages
+    /// dependencies. So enabling this flag will enable building the "type": "dev" source folders
+    /// of _all_ packages
     #[arg(long, default_value_t = false, num_args = 0..=1)]
     dev: bool,
 
@@ -79,6 +97,70 @@ struct Args {
     /// A custom path to bsc
     #[arg(long)]
     bsc_path: Option<String>,
+
+    /// How to report the errors and warnings of the compiler. With 'json', build prints a JSON
+    /// array with a record per diagnostic, and watch prints a JSON record per line (NDJSON) after
+    /// every compilation. The progress output is turned off.
+    #[arg(long, value_enum, default_value_t = Reporter::Human)]
+    reporter: Reporter,
+
+    /// Write the errors and warnings of the compiler to a SARIF file, for instance to annotate
+    /// pull requests with GitHub code scanning. Only used by build.
+    #[arg(long)]
+    sarif: Option<String>,
+
+    /// Share the outputs of the compiler between builds, for instance between CI jobs. The
+    /// location is a local directory, or an http:// url of a server that supports GET and PUT of
+    /// <url>/<key>. Only used by build.
+    #[arg(long)]
+    artifact_cache: Option<String>,
+
+    /// Print the source files that would be parsed and the modules that would be compiled, and
+    /// why, without parsing or compiling anything. Only used by build.
+    #[arg(long, default_value_t = false, num_args = 0..=1)]
+    dry_run: bool,
+
+    /// Print the modules that were compiled, and why. Only used by build.
+    #[arg(long, default_value_t = false, num_args = 0..=1)]
+    explain: bool,
+
+    /// Write the time it took to parse and compile every module, and the phases of the build, to a
+    /// file in the Chrome trace event format, which can be opened in Perfetto. Only used by build.
+    #[arg(long)]
+    profile: Option<String>,
+
+    /// The format of the dependency graph. Only used by graph.
+    #[arg(long, value_enum, default_value_t = graph::Format::Dot)]
+    format: graph::Format,
+
+    /// Print the dependencies between the packages instead of the modules. Only used by graph.
+    #[arg(long, default_value_t = false, num_args = 0..=1)]
+    package_graph: bool,
+
+    /// Only include the modules of this package (can be repeated). With build, only this package
+    /// and the packages it depends on are built. Only used by build and graph.
+    #[arg(long)]
+    package: Vec<String>,
+
+    /// Only compile this module and the modules it depends on, transitively (can be repeated).
+    /// The sources of all modules are still parsed, to find what the module depends on. Overrides
+    /// the "entries" in the config of the root package. Only used by build.
+    #[arg(long)]
+    entry: Vec<String>,
+
+    /// The git revision to compare with, to find the changed files. Only used by affected.
+    #[arg(long)]
+    since: Option<String>,
+
+    /// Only include this module (or package) and everything it depends on, transitively. Only
+    /// used by graph.
+    #[arg(long)]
+    deps_of: Option<String>,
+
+    /// Only include this module (or package) and everything that depends on it, transitively.
+    /// Only used by graph.
+    #[arg(long)]
+    dependents_of: Option<String>,
 }
 
 fn main() -> Result<()> {
@@ -88,11 +170,35 @@ fn main() -> Result<()> {
     env_logger::Builder::new()
         .format(|buf, record| writeln!(buf, "{}:\n{}", record.level(), record.args()))
         .filter_level(log_level_filter)
-        .target(env_logger::fmt::Target::Stdout)
+        .target(if args.reporter.is_json() {
+            // stdout is reserved for the diagnostics
+            env_logger::fmt::Target::Stderr
+        } else {
+            env_logger::fmt::Target::Stdout
+        })
         .init();
 
     let command = args.command.unwrap_or(Command::Build);
-    let folder = args.folder.unwrap_or(".".to_string());
+    // the folder is optional, so with two arguments why gets two modules
+    let (folder, modules) = match (&command, args.folder, args.modules) {
+        (Command::Why, Some(module), modules) if modules.len() == 1 => {
+            (None, [vec![module], modules].concat())
+        }
+        (_, folder, modules) => (folder, modules),
+    };
+    // only why takes modules, the other commands would silently ignore them
+    if !matches!(command, Command::Why) && !modules.is_empty() {
+        Args::command()
+            .error(
+                ErrorKind::UnknownArgument,
+                format!(
+                    "unexpected argument '{}', only why takes modules",
+                    modules.join(" ")
+                ),
+            )
+            .exit();
+    }
+    let folder = folder.unwrap_or(".".to_string());
     let filter = args
         .filter
         .map(|filter| Regex::new(filter.as_ref()).expect("Could not parse regex"));
@@ -110,7 +216,7 @@ fn main() -> Result<()> {
 
     // The 'normal run' mode will show the 'pretty' formatted progress. But if we turn off the log
     // level, we should never show that.
-    let show_progress = log_level_filter == LevelFilter::Info;
+    let show_progress = log_level_filter == LevelFilter::Info && !args.reporter.is_json();
 
     match lock::get(&folder) {
         lock::Lock::Error(ref e) => {
@@ -119,6 +225,22 @@ fn main() -> Result<()> {
         }
         lock::Lock::Aquired(_) => match command {
             Command::Clean => build::clean::clean(&folder, show_progress, args.bsc_path, args.dev),
+            Command::Build if args.dry_run => {
+                match build::dry_run::run(
+                    &filter,
+                    &folder,
+                    args.bsc_path,
+                    args.dev,
+                    &args.package,
+                    &args.entry,
+                ) {
+                    Err(e) => {
+                        println!("{e}");
+                        std::process::exit(1)
+                    }
+                    Ok(_) => std::process::exit(0),
+                }
+            }
             Command::Build => {
                 match build::build(
                     &filter,
@@ -128,9 +250,20 @@ fn main() -> Result<()> {
                     args.create_sourcedirs,
                     args.bsc_path,
                     args.dev,
+                    args.reporter,
+                    args.sarif,
+                    args.artifact_cache,
+                    &args.package,
+                    &args.entry,
+                    args.explain,
+                    args.profile,
                 ) {
                     Err(e) => {
-                        println!("{e}");
+                        if args.reporter.is_json() {
+                            eprintln!("{e}");
+                        } else {
+                            println!("{e}");
+                        }
                         std::process::exit(1)
                     }
                     Ok(_) => {
@@ -141,6 +274,74 @@ fn main() -> Result<()> {
                     }
                 };
             }
+            Command::Graph => {
+                match build::get_dependency_state(&folder, args.bsc_path, args.dev).and_then(|build_state| {
+                    graph::print(
+                        &build_state,
+                        args.format,
+                        args.package_graph,
+                        &args.package,
+                        &args.deps_of,
+                        &args.dependents_of,
+                    )
+                    .map_err(|e| anyhow::anyhow!(e))
+                }) {
+                    Err(e) => {
+                        eprintln!("{e}");
+                        std::process::exit(1)
+                    }
+                    Ok(_) => std::process::exit(0),
+                }
+            }
+            Command::Why => match modules.as_slice() {
+                [from, to] => match build::get_dependency_state(&folder, args.bsc_path, args.dev).and_then(
+                    |build_state| graph::why(&build_state, from, to).map_err(|e| anyhow::anyhow!(e)),
+                ) {
+                    Err(e) => {
+                        eprintln!("{e}");
+                        std::process::exit(1)
+                    }
+                    Ok(explanation) => {
+                        println!("{explanation}");
+                        std::process::exit(0)
+                    }
+                },
+                _ => {
+                    eprintln!("Expected two modules: rewatch why <MODULE_A> <MODULE_B>");
+                    std::process::exit(1)
+                }
+            },
+            Command::CheckDeps => match build::get_dependency_state(&folder, args.bsc_path, true) {
+                Err(e) => {
+                    eprintln!("{e}");
+                    std::process::exit(1)
+                }
+                Ok(build_state) => std::process::exit(if check_deps::check(&build_state) { 0 } else { 1 }),
+            },
+            Command::Affected => {
+                let changed_files = match &args.since {
+                    Some(since) => affected::get_changed_files_since(&folder, since),
+                    None => Ok(affected::read_changed_files(
+                        std::io::stdin().lock(),
+                        &affected::get_changed_files_base(&folder)?,
+                    )),
+                };
+                match changed_files
+                    .map_err(|e| anyhow::anyhow!(e))
+                    .and_then(|changed_files| {
+                        build::get_dependency_state(&folder, args.bsc_path, args.dev)
+                            .map(|build_state| affected::find(&build_state, &changed_files))
+                    }) {
+                    Err(e) => {
+                        eprintln!("{e}");
+                        std::process::exit(1)
+                    }
+                    Ok(affected) => {
+                        println!("{}", affected.to_json());
+                        std::process::exit(0)
+                    }
+                }
+            }
             Command::Watch => {
                 watcher::start(
                     &filter,
@@ -150,6 +351,7 @@ fn main() -> Result<()> {
                     args.create_sourcedirs,
                     args.dev,
                     args.bsc_path,
+                    args.reporter,
                 );
 
                 Ok(())
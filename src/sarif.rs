@@ -0,0 +1,181 @@
+use crate::build::build_types::BuildState;
+use crate::build::diagnostics::{Diagnostic, Severity};
+use serde_json::{json, Value};
+use std::collections::BTreeSet;
+use std::fs::File;
+use std::io::prelude::*;
+use std::path::Path;
+
+// Writes the diagnostics of the build as a SARIF 2.1.0 log, so code scanning tools can annotate
+// the sources with the warnings and errors of the compiler.
+// See https://docs.oasis-open.org/sarif/sarif/v2.1.0/sarif-v2.1.0.html
+
+static PROJECT_ROOT: &str = "PROJECTROOT";
+
+fn get_rule_id(diagnostic: &Diagnostic) -> String {
+    match diagnostic.warning_number {
+        Some(warning_number) => format!("warning-{}", warning_number),
+        None => "error".to_string(),
+    }
+}
+
+fn get_rule(rule_id: &str) -> Value {
+    let description = match rule_id.strip_prefix("warning-") {
+        Some(warning_number) => format!("Warning number {}", warning_number),
+        None => "Compiler error".to_string(),
+    };
+    json!({
+        "id": rule_id,
+        "shortDescription": { "text": description },
+    })
+}
+
+// files in the project are relative to the project root, other files (for instance in a
+// workspace root) get an absolute uri
+fn get_artifact_location(file: &str, project_root: &str) -> Value {
+    match Path::new(file).strip_prefix(project_root) {
+        Ok(relative_path) => json!({
+            "uri": relative_path.to_string_lossy().replace('\\', "/"),
+            "uriBaseId": PROJECT_ROOT,
+        }),
+        Err(_) => json!({
+            "uri": to_file_uri(file),
+        }),
+    }
+}
+
+fn to_file_uri(path: &str) -> String {
+    let path = path.replace('\\', "/");
+    if path.starts_with('/') {
+        format!("file://{}", path)
+    } else {
+        format!("file:///{}", path)
+    }
+}
+
+fn get_result(diagnostic: &Diagnostic, rule_ids: &[String], project_root: &str) -> Value {
+    let rule_id = get_rule_id(diagnostic);
+    json!({
+        "ruleId": rule_id,
+        "ruleIndex": rule_ids.iter().position(|id| id == &rule_id),
+        "level": match diagnostic.severity {
+            Severity::Error => "error",
+            Severity::Warning => "warning",
+        },
+        "message": { "text": diagnostic.message },
+        "locations": [{
+            "physicalLocation": {
+                "artifactLocation": get_artifact_location(&diagnostic.file, project_root),
+                "region": {
+                    "startLine": diagnostic.range.start.line,
+                    "startColumn": diagnostic.range.start.column,
+                    "endLine": diagnostic.range.end.line,
+                    // the end column of bsc is inclusive, in SARIF it is exclusive
+                    "endColumn": diagnostic.range.end.column + 1,
+                },
+            },
+        }],
+        "properties": {
+            "package": diagnostic.package,
+            "module": diagnostic.module,
+        },
+    })
+}
+
+pub fn to_sarif(diagnostics: &[Diagnostic], project_root: &str) -> Value {
+    let rule_ids = diagnostics
+        .iter()
+        .map(get_rule_id)
+        .collect::<BTreeSet<String>>()
+        .into_iter()
+        .collect::<Vec<String>>();
+
+    json!({
+        "$schema": "https://json.schemastore.org/sarif-2.1.0.json",
+        "version": "2.1.0",
+        "runs": [{
+            "tool": {
+                "driver": {
+                    "name": "rewatch",
+                    "version": env!("CARGO_PKG_VERSION"),
+                    "informationUri": "https://github.com/rescript-lang/rewatch",
+                    "rules": rule_ids.iter().map(|rule_id| get_rule(rule_id)).collect::<Vec<Value>>(),
+                },
+            },
+            "originalUriBaseIds": {
+                PROJECT_ROOT: { "uri": to_file_uri(project_root).trim_end_matches('/').to_string() + "/" },
+            },
+            "results": diagnostics
+                .iter()
+                .map(|diagnostic| get_result(diagnostic, &rule_ids, project_root))
+                .collect::<Vec<Value>>(),
+        }],
+    })
+}
+
+pub fn write(build_state: &BuildState, path: &str) {
+    let mut sarif_file = File::create(path).expect("Could not create SARIF file");
+    sarif_file
+        .write_all(
+            to_sarif(&build_state.diagnostics, &build_state.project_root)
+                .to_string()
+                .as_bytes(),
+        )
+        .expect("Could not write SARIF file");
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::build::diagnostics::{Position, Range};
+
+    fn create_diagnostic(file: &str, warning_number: Option<u32>, severity: Severity) -> Diagnostic {
+        Diagnostic {
+            file: file.to_string(),
+            range: Range {
+                start: Position { line: 4, column: 8 },
+                end: Position { line: 4, column: 24 },
+            },
+            severity,
+            warning_number,
+            package: "main".to_string(),
+            module: "Main".to_string(),
+            message: "unused variable x.".to_string(),
+        }
+    }
+
+    #[test]
+    fn test_to_sarif() {
+        let sarif = to_sarif(
+            &[
+                create_diagnostic("/project/src/Main.res", Some(27), Severity::Warning),
+                create_diagnostic("/workspace/node_modules/dep/src/Dep.res", None, Severity::Error),
+            ],
+            "/project",
+        );
+        let run = &sarif["runs"][0];
+
+        assert_eq!(sarif["version"], "2.1.0");
+        assert_eq!(
+            run["originalUriBaseIds"]["PROJECTROOT"]["uri"],
+            "file:///project/"
+        );
+        assert_eq!(run["tool"]["driver"]["rules"][0]["id"], "error");
+        assert_eq!(run["tool"]["driver"]["rules"][1]["id"], "warning-27");
+
+        let result = &run["results"][0];
+        assert_eq!(result["ruleId"], "warning-27");
+        assert_eq!(result["ruleIndex"], 1);
+        assert_eq!(result["level"], "warning");
+        let location = &result["locations"][0]["physicalLocation"];
+        assert_eq!(location["artifactLocation"]["uri"], "src/Main.res");
+        assert_eq!(location["artifactLocation"]["uriBaseId"], "PROJECTROOT");
+        assert_eq!(location["region"]["endColumn"], 25);
+
+        let location = &run["results"][1]["locations"][0]["physicalLocation"];
+        assert_eq!(
+            location["artifactLocation"]["uri"],
+            "file:///workspace/node_modules/dep/src/Dep.res"
+        );
+    }
+}
@@ -36,6 +36,20 @@ fn package_to_dirs(package: &Package, root_package_path: &String) -> AHashSet<Di
         .collect::<AHashSet<String>>()
 }
 
+fn package_to_generated(package: &Package, root_package_path: &String) -> AHashSet<String> {
+    let relative_path = PathBuf::from(&package.path)
+        .strip_prefix(PathBuf::from(&root_package_path))
+        .unwrap()
+        .to_string_lossy()
+        .to_string();
+
+    package
+        .get_generated_files()
+        .iter()
+        .map(|path| format!("{relative_path}/{path}"))
+        .collect::<AHashSet<String>>()
+}
+
 fn deps_to_pkgs<'a>(
     packages: &'a AHashMap<String, Package>,
     dependencies: &'a Option<Vec<String>>,
@@ -66,7 +80,12 @@ pub fn print(buildstate: &BuildState) {
         .expect("Could not find root package");
 
     // Take all packages apart from the root package
-    let (dirs, pkgs): (Vec<AHashSet<Dir>>, Vec<AHashMap<PackageName, AbsolutePath>>) = buildstate
+    type PackageSourceDirs = (
+        AHashSet<Dir>,
+        AHashMap<PackageName, AbsolutePath>,
+        AHashSet<String>,
+    );
+    let source_dirs: Vec<PackageSourceDirs> = buildstate
         .packages
         .par_iter()
         .filter(|(_name, package)| !package.is_root)
@@ -74,6 +93,9 @@ pub fn print(buildstate: &BuildState) {
             // Extract Directories
             let dirs = package_to_dirs(package, &root_package.path);
 
+            // Extract Generated Files
+            let generated = package_to_generated(package, &root_package.path);
+
             // Extract Pkgs
             let pkgs = [
                 &package.config.pinned_dependencies,
@@ -89,7 +111,7 @@ pub fn print(buildstate: &BuildState) {
                 &SourceDirs {
                     dirs: &dirs.clone().into_iter().collect::<Vec<Dir>>(),
                     pkgs: &pkgs.clone().flatten().collect::<Vec<Pkg>>(),
-                    generated: &vec![],
+                    generated: &generated.clone().into_iter().collect::<Vec<String>>(),
                 },
             )
             .expect("Could not write sourcedirs.json");
@@ -97,15 +119,22 @@ pub fn print(buildstate: &BuildState) {
             (
                 dirs,
                 pkgs.flatten().collect::<AHashMap<PackageName, AbsolutePath>>(),
+                generated,
             )
         })
-        .unzip();
+        .collect();
 
     let mut merged_dirs: AHashSet<Dir> = AHashSet::new();
     let mut merged_pkgs: AHashMap<PackageName, AbsolutePath> = AHashMap::new();
+    let mut merged_generated: AHashSet<String> = AHashSet::new();
 
-    dirs.into_iter().for_each(|dir_set| merged_dirs.extend(dir_set));
-    pkgs.into_iter().for_each(|pkg_set| merged_pkgs.extend(pkg_set));
+    source_dirs
+        .into_iter()
+        .for_each(|(dir_set, pkg_set, generated_set)| {
+            merged_dirs.extend(dir_set);
+            merged_pkgs.extend(pkg_set);
+            merged_generated.extend(generated_set);
+        });
 
     // Write sourcedirs.json
     write_sourcedirs_files(
@@ -113,7 +142,7 @@ pub fn print(buildstate: &BuildState) {
         &SourceDirs {
             dirs: &merged_dirs.into_iter().collect::<Vec<Dir>>(),
             pkgs: &merged_pkgs.into_iter().collect::<Vec<Pkg>>(),
-            generated: &vec![],
+            generated: &merged_generated.into_iter().collect::<Vec<String>>(),
         },
     )
     .expect("Could not write sourcedirs.json");
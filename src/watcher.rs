@@ -1,6 +1,9 @@
 use crate::build;
-use crate::build::build_types::SourceType;
+use crate::build::build_types::{BuildState, SourceType};
 use crate::build::clean;
+use crate::build::diagnostics::{self, Reporter};
+use crate::build::packages::Package;
+use crate::build::state_cache;
 use crate::cmd;
 use crate::helpers;
 use crate::helpers::emojis::*;
@@ -31,6 +34,18 @@ fn is_rescript_file(path_buf: &Path) -> bool {
     }
 }
 
+// saving a file without changing it (or changing it back) doesn't need a build
+fn is_source_unchanged(
+    previous_build: &Option<state_cache::PreviousBuild>,
+    module_name: &str,
+    package: &Package,
+    path: &str,
+) -> bool {
+    previous_build.as_ref().is_some_and(|previous_build| {
+        state_cache::is_source_unchanged(previous_build, module_name, package, path)
+    })
+}
+
 fn is_in_build_path(path_buf: &Path) -> bool {
     path_buf
         .to_str()
@@ -38,6 +53,45 @@ fn is_in_build_path(path_buf: &Path) -> bool {
         .unwrap_or(false)
 }
 
+// a path is ignored when it is inside one of the `ignored-dirs` of the package it belongs to. When
+// packages are nested (for instance the root package and a local package), the most specific one
+// is the one that owns the path
+fn is_in_ignored_dir(path_buf: &Path, build_state: &BuildState) -> bool {
+    let path_buf = path_buf.canonicalize().unwrap_or(path_buf.to_path_buf());
+    build_state
+        .packages
+        .values()
+        .filter_map(|package| {
+            path_buf
+                .strip_prefix(&package.path)
+                .ok()
+                .map(|relative_path| (package, relative_path))
+        })
+        .max_by_key(|(package, _)| package.path.len())
+        .map(|(package, relative_path)| {
+            relative_path
+                .parent()
+                .map(|dir| package.config.is_ignored_path(dir))
+                .unwrap_or(false)
+        })
+        .unwrap_or(false)
+}
+
+// changes to the inputs of generators also need a rebuild, so the generators run again
+fn is_generator_input(path_buf: &Path, build_state: &BuildState) -> bool {
+    let path_buf = path_buf.canonicalize().unwrap_or(path_buf.to_path_buf());
+    build_state.packages.values().any(|package| {
+        package.source_folders.iter().any(|source| {
+            source.generators.iter().flatten().any(|generator| {
+                generator
+                    .get_inputs()
+                    .iter()
+                    .any(|input| Path::new(&package.path).join(&source.dir).join(input) == path_buf)
+            })
+        })
+    })
+}
+
 fn matches_filter(path_buf: &Path, filter: &Option<regex::Regex>) -> bool {
     let name = path_buf
         .file_name()
@@ -46,6 +100,7 @@ fn matches_filter(path_buf: &Path, filter: &Option<regex::Regex>) -> bool {
     filter.as_ref().map(|re| !re.is_match(&name)).unwrap_or(true)
 }
 
+#[allow(clippy::too_many_arguments)]
 async fn async_watch(
     q: Arc<FifoQueue<Result<Event, Error>>>,
     path: &str,
@@ -55,6 +110,7 @@ async fn async_watch(
     create_sourcedirs: bool,
     build_dev_deps: bool,
     bsc_path: Option<String>,
+    reporter: Reporter,
 ) -> notify::Result<()> {
     let mut build_state = build::initialize_build(
         None,
@@ -63,9 +119,13 @@ async fn async_watch(
         path,
         bsc_path.clone(),
         build_dev_deps,
+        false,
+        false,
     )
     .expect("Can't initialize build");
     let mut needs_compile_type = CompileType::Incremental;
+    // the state of the last build, to only mark the files dirty of which the content changed
+    let mut previous_build = None;
     // create a mutex to capture if ctrl-c was pressed
     let ctrlc_pressed = Arc::new(Mutex::new(false));
     let ctrlc_pressed_clone = Arc::clone(&ctrlc_pressed);
@@ -116,9 +176,11 @@ async fn async_watch(
             let paths = event
                 .paths
                 .iter()
-                .filter(|path| is_rescript_file(path))
+                .filter(|path| is_rescript_file(path) || is_generator_input(path, &build_state))
                 .filter(|path| !is_in_build_path(path))
-                .filter(|path| matches_filter(path, filter));
+                .filter(|path| matches_filter(path, filter))
+                .filter(|path| !is_in_ignored_dir(path, &build_state))
+                .collect::<Vec<_>>();
             for path in paths {
                 let path_buf = path.to_path_buf();
 
@@ -144,7 +206,7 @@ async fn async_watch(
                         // if we are going to compile incrementally, we need to mark the exact files
                         // dirty
                         if let Ok(canonicalized_path_buf) = path_buf.canonicalize() {
-                            for module in build_state.modules.values_mut() {
+                            for (module_name, module) in build_state.modules.iter_mut() {
                                 match module.source_type {
                                     SourceType::SourceFile(ref mut source_file) => {
                                         // mark the implementation file dirty
@@ -161,7 +223,14 @@ async fn async_watch(
                                             {
                                                 source_file.implementation.last_modified = modified;
                                             };
-                                            source_file.implementation.parse_dirty = true;
+                                            if !is_source_unchanged(
+                                                &previous_build,
+                                                module_name,
+                                                package,
+                                                &source_file.implementation.path,
+                                            ) {
+                                                source_file.implementation.parse_dirty = true;
+                                            }
                                             break;
                                         }
 
@@ -177,7 +246,14 @@ async fn async_watch(
                                                 {
                                                     interface.last_modified = modified;
                                                 }
-                                                interface.parse_dirty = true;
+                                                if !is_source_unchanged(
+                                                    &previous_build,
+                                                    module_name,
+                                                    package,
+                                                    &interface.path,
+                                                ) {
+                                                    interface.parse_dirty = true;
+                                                }
                                                 break;
                                             }
                                         }
@@ -206,7 +282,7 @@ async fn async_watch(
         match needs_compile_type {
             CompileType::Incremental => {
                 let timing_total = Instant::now();
-                if build::incremental_build(
+                let result = build::incremental_build(
                     &mut build_state,
                     None,
                     initial_build,
@@ -214,9 +290,13 @@ async fn async_watch(
                     !initial_build,
                     create_sourcedirs,
                     build_dev_deps,
-                )
-                .is_ok()
-                {
+                    reporter,
+                );
+                if reporter.is_json() {
+                    diagnostics::print_ndjson(&build_state.diagnostics);
+                }
+                previous_build = state_cache::write(&build_state, build_dev_deps);
+                if result.is_ok() {
                     if let Some(a) = after_build.clone() {
                         cmd::run(a)
                     }
@@ -243,6 +323,8 @@ async fn async_watch(
                     path,
                     bsc_path.clone(),
                     build_dev_deps,
+                    false,
+                    false,
                 )
                 .expect("Can't initialize build");
                 let _ = build::incremental_build(
@@ -253,7 +335,12 @@ async fn async_watch(
                     false,
                     create_sourcedirs,
                     build_dev_deps,
+                    reporter,
                 );
+                if reporter.is_json() {
+                    diagnostics::print_ndjson(&build_state.diagnostics);
+                }
+                previous_build = state_cache::write(&build_state, build_dev_deps);
                 if let Some(a) = after_build.clone() {
                     cmd::run(a)
                 }
@@ -289,6 +376,7 @@ pub fn start(
     create_sourcedirs: bool,
     build_dev_deps: bool,
     bsc_path: Option<String>,
+    reporter: Reporter,
 ) {
     futures::executor::block_on(async {
         let queue = Arc::new(FifoQueue::<Result<Event, Error>>::new());
@@ -310,6 +398,7 @@ pub fn start(
             create_sourcedirs,
             build_dev_deps,
             bsc_path,
+            reporter,
         )
         .await
         {